@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+// Shared timing for everything that gets animated step by step (generation and solving)
+pub struct Playback
+{
+    pub playing: bool,
+    pub interval: Duration,
+    pub steps_per_tick: usize,
+    timer: Instant,
+    queued_steps: usize,
+}
+
+impl Playback
+{
+    pub fn new(interval: Duration) -> Self
+    {
+        Playback
+        {
+            playing: false,
+            interval,
+            steps_per_tick: 1,
+            timer: Instant::now(),
+            queued_steps: 0,
+        }
+    }
+
+    // How many steps should run this frame
+    pub fn tick(&mut self) -> usize
+    {
+        let mut steps = std::mem::take(&mut self.queued_steps);
+
+        if self.playing && self.timer.elapsed() >= self.interval
+        {
+            steps += self.steps_per_tick.max(1);
+            self.timer = Instant::now();
+        }
+
+        steps
+    }
+
+    pub fn toggle(&mut self)
+    {
+        self.playing = !self.playing;
+        self.timer = Instant::now();
+    }
+
+    // Single step, also works while paused
+    pub fn step_once(&mut self)
+    {
+        self.queued_steps += 1;
+    }
+}
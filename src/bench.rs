@@ -0,0 +1,125 @@
+use std::time::{Duration, Instant};
+
+use macroquad::prelude::*;
+
+use crate::{GridConfig, fill, generator::{Generator, Phase}, image::InputGrid, symmetry::Symmetry};
+
+const USAGE: &str = "Usage: maze_images --bench [--grid 2000x2000] [--seed <n>] [--runs 1]";
+
+// Times every generation phase on a plain maze and on one with a long image path,
+// the path is a serpentine through every other row so the shortcut pass has the most to do
+// Then the canvas fill on a 4K image, which doesn't depend on the grid
+pub fn run(args: &[String]) -> Result<(), String>
+{
+    let (mut grid_width, mut grid_height) = (2000, 2000);
+    let mut seed = 1;
+    let mut runs = 1;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next()
+    {
+        let value = args.next().ok_or(format!("Missing value for {}\n{}", arg, USAGE))?;
+        match arg.as_str()
+        {
+            "--grid" =>
+            {
+                let (w, h) = value.split_once('x').ok_or(USAGE)?;
+                (grid_width, grid_height) = (w.parse().map_err(|_| USAGE)?, h.parse().map_err(|_| USAGE)?);
+            },
+            "--seed" => seed = value.parse().map_err(|_| USAGE)?,
+            "--runs" => runs = value.parse::<usize>().map_err(|_| USAGE)?.max(1),
+            other => return Err(format!("Unknown argument {}\n{}", other, USAGE)),
+        }
+    }
+    if grid_width < 2 || grid_height < 2 { return Err(USAGE.to_owned()); }
+
+    let grid_config = GridConfig::new(0.0, 0.0, grid_width, grid_height, 1.0, (0.0, 0.0));
+    let ms = |d: Duration| format!("{:.1}ms", d.as_secs_f64() * 1000.0);
+    println!("{}x{} ({} cells), {} run(s)", grid_width, grid_height, grid_config.grid_size, runs);
+    println!("{:<12} {:>10} {:>10} {:>10} {:>10} {:>10}  checksum", "input", "setup", "carving", "path", "shortcuts", "total");
+
+    for (name, input) in [("none", None), ("serpentine", Some(serpentine(grid_width, grid_height)))]
+    {
+        for _ in 0..runs
+        {
+            let (times, checksum) = time_phases(input.clone(), seed, &grid_config);
+            let total: Duration = times.iter().sum();
+            println!("{:<12} {:>10} {:>10} {:>10} {:>10} {:>10}  {:016x}", name, ms(times[0]), ms(times[1]), ms(times[2]), ms(times[3]), ms(total), checksum);
+        }
+    }
+
+    println!();
+    println!("{:<12} {:>10}", "canvas", "fill");
+    for _ in 0..runs
+    {
+        println!("{:<12} {:>10}", "4k strokes", ms(time_fill()));
+    }
+
+    Ok(())
+}
+
+// The reason for the scanline fill, a 4K canvas with a few strokes on it, filled around them
+fn time_fill() -> Duration
+{
+    let (width, height) = (3840, 2160);
+    let mut image = Image::gen_image_color(width, height, BLANK);
+    for x in (0..width).step_by(97)
+    {
+        for y in 0..height - 300 { image.set_pixel(x as u32, y as u32, WHITE); }
+    }
+
+    let timer = Instant::now();
+    let filled = fill::flood(&image, (1, 1), 0.1, false);
+    fill::paint(&mut image, &filled, [0, 0, 0, 0], 0.1, RED);
+    timer.elapsed()
+}
+
+// Setup, carving, path carving and shortcut removal, plus a hash of the result
+// The hash only changes if the generated maze does
+fn time_phases(input: Option<Vec<bool>>, seed: u64, grid_config: &GridConfig) -> ([Duration; 4], u64)
+{
+    let mut times = [Duration::ZERO; 4];
+
+    let timer = Instant::now();
+    let mut generator = Generator::new(input.map(InputGrid::from_path), seed, Symmetry::None, grid_config);
+    times[0] = timer.elapsed();
+
+    let mut timer = Instant::now();
+    let mut phase = generator.phase;
+    loop
+    {
+        let done = generator.step();
+        if generator.phase != phase || done
+        {
+            let index = match phase { Phase::Carving => 1, Phase::PathCarving => 2, _ => 3 };
+            times[index] += timer.elapsed();
+            timer = Instant::now();
+            phase = generator.phase;
+        }
+        if done { break; }
+    }
+
+    // FNV-1a over the walls
+    let checksum = generator.grid.iter().fold(0xcbf29ce484222325u64, |hash, cell| (hash ^ cell.to_bits() as u64).wrapping_mul(0x100000001b3));
+    (times, checksum)
+}
+
+// Every even row, joined at alternating ends, so it's one path with exactly two ends
+pub fn serpentine(grid_width: usize, grid_height: usize) -> Vec<bool>
+{
+    let mut path = vec![false; grid_width * grid_height];
+    let rows = (grid_height - 1) / 2 * 2;
+
+    for y in (0..=rows).step_by(2)
+    {
+        for x in 0..grid_width { path[y * grid_width + x] = true; }
+
+        if y + 2 <= rows
+        {
+            let x = if (y / 2).is_multiple_of(2) { grid_width - 1 } else { 0 };
+            path[(y + 1) * grid_width + x] = true;
+        }
+    }
+
+    path
+}
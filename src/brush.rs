@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::canvas::BrushSettings;
+
+const SPEED_REFERENCE: f32 = 1500.0; // Canvas pixels a second where full speed thinning halves the width
+const MIN_WIDTH: f32 = 0.2; // Of the brush size, for fast strokes and the tapered tips
+const MAX_AVERAGE: usize = 32; // Mouse positions averaged at full smoothing
+const MAX_ROPE: f32 = 60.0; // Canvas pixels of slack at full smoothing
+const SOFT_FLOW: f32 = 0.35; // How much one soft dab covers, overlapping dabs build up
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum BrushTip
+{
+    Hard,
+    Soft,
+    Square,
+}
+
+impl BrushTip
+{
+    pub const ALL: [BrushTip; 3] = [BrushTip::Hard, BrushTip::Soft, BrushTip::Square];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            BrushTip::Hard => "Hard",
+            BrushTip::Soft => "Soft",
+            BrushTip::Square => "Square",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Stabilizer
+{
+    Smooth,  // Pen eases towards the mouse
+    Average, // Pen at the average of the last mouse positions
+    Rope,    // Pen only moves once the mouse pulls the rope tight, good for clean corners
+}
+
+impl Stabilizer
+{
+    pub const ALL: [Stabilizer; 3] = [Stabilizer::Smooth, Stabilizer::Average, Stabilizer::Rope];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Stabilizer::Smooth => "Smooth",
+            Stabilizer::Average => "Average",
+            Stabilizer::Rope => "Rope",
+        }
+    }
+}
+
+// One freehand stroke, from press to release
+pub struct Stroke
+{
+    pub points: Vec<(Vec2, f32)>, // Pen position and width, kept so the ends can be tapered once it's done
+    recent: VecDeque<Vec2>, // Mouse positions, for the average stabilizer
+    pen: Vec2,
+    speed: f32,
+    length: f32,
+}
+
+impl Stroke
+{
+    pub fn new(start: Vec2) -> Self
+    {
+        Stroke { points: Vec::new(), recent: VecDeque::new(), pen: start, speed: 0.0, length: 0.0 }
+    }
+
+    // Where the pen is for this mouse position
+    pub fn follow(&mut self, mouse: Vec2, brush: &BrushSettings) -> Vec2
+    {
+        // Smoothing is 1 for none, down to almost 0 for the most
+        let strength = 1.0 - brush.smoothing.clamp(0.0, 1.0);
+
+        self.pen = match brush.stabilizer
+        {
+            Stabilizer::Smooth => self.pen.lerp(mouse, brush.smoothing),
+            Stabilizer::Average =>
+            {
+                self.recent.push_back(mouse);
+                let window = 1 + (strength * MAX_AVERAGE as f32) as usize;
+                while self.recent.len() > window { self.recent.pop_front(); }
+
+                self.recent.iter().copied().sum::<Vec2>() / self.recent.len() as f32
+            },
+            Stabilizer::Rope =>
+            {
+                let rope = strength * MAX_ROPE;
+                let pull = mouse - self.pen;
+                if pull.length() > rope { mouse - pull.normalize() * rope } else { self.pen }
+            },
+        };
+        self.pen
+    }
+
+    // Adds the pen position, its width is thinner the faster the pen moves and grows in over the taper length
+    pub fn add(&mut self, pen: Vec2, brush: &BrushSettings) -> f32
+    {
+        let moved = self.points.last().map_or(0.0, |&(last, _)| last.distance(pen));
+        self.length += moved;
+
+        let speed = moved / get_frame_time().max(0.001);
+        self.speed += (speed - self.speed) * 0.3;
+
+        let width = (brush.size / (1.0 + brush.speed_thinning * self.speed / SPEED_REFERENCE)).max(brush.size * MIN_WIDTH);
+        self.points.push((pen, width));
+
+        width * taper(self.length, brush.taper)
+    }
+
+    // The last pen position and the width it was drawn with
+    pub fn last(&self, brush: &BrushSettings) -> Option<(Vec2, f32)>
+    {
+        self.points.last().map(|&(pen, width)| (pen, width * taper(self.length, brush.taper)))
+    }
+
+    // Every point with both ends narrowed down over the taper length
+    pub fn tapered(&self, taper_length: f32) -> Vec<(Vec2, f32)>
+    {
+        let mut along = 0.0;
+        let mut last = self.points.first().map_or(Vec2::ZERO, |&(pen, _)| pen);
+
+        self.points.iter().map(|&(pen, width)|
+        {
+            along += last.distance(pen);
+            last = pen;
+
+            (pen, width * taper(along, taper_length).min(taper(self.length - along, taper_length)))
+        }).collect()
+    }
+}
+
+fn taper(distance: f32, taper_length: f32) -> f32
+{
+    if taper_length <= 0.0 { return 1.0; }
+    (distance / taper_length).clamp(MIN_WIDTH, 1.0)
+}
+
+// One stamp of the tip into the image
+pub fn dab(image: &mut Image, pos: Vec2, radius: f32, color: Color, tip: BrushTip)
+{
+    let r = radius.max(0.5);
+    let (width, height) = (image.width() as i32, image.height() as i32);
+
+    let (min_x, max_x) = (((pos.x - r).floor() as i32).max(0), ((pos.x + r).ceil() as i32).min(width - 1));
+    let (min_y, max_y) = (((pos.y - r).floor() as i32).max(0), ((pos.y + r).ceil() as i32).min(height - 1));
+    let center = vec2(pos.x.floor(), pos.y.floor());
+
+    for y in min_y..=max_y
+    {
+        for x in min_x..=max_x
+        {
+            let offset = vec2(x as f32, y as f32) - center;
+
+            match tip
+            {
+                BrushTip::Hard => if offset.length_squared() <= r * r { image.set_pixel(x as u32, y as u32, color); },
+                BrushTip::Square => if offset.x.abs() <= r && offset.y.abs() <= r { image.set_pixel(x as u32, y as u32, color); },
+                BrushTip::Soft =>
+                {
+                    let coverage = 1.0 - offset.length() / r;
+                    if coverage > 0.0 { blend(image, x as u32, y as u32, color, coverage * coverage * SOFT_FLOW); }
+                },
+            }
+        }
+    }
+}
+
+// Color laid over the pixel, a see through color (the eraser) takes away instead
+fn blend(image: &mut Image, x: u32, y: u32, color: Color, amount: f32)
+{
+    let below = image.get_pixel(x, y);
+
+    if color.a <= 0.0
+    {
+        image.set_pixel(x, y, Color { a: below.a * (1.0 - amount), ..below });
+        return;
+    }
+
+    let a = amount * color.a;
+    let out_a = a + below.a * (1.0 - a);
+    let mix = |top: f32, bottom: f32| (top * a + bottom * below.a * (1.0 - a)) / out_a;
+
+    image.set_pixel(x, y, Color::new(mix(color.r, below.r), mix(color.g, below.g), mix(color.b, below.b), out_a));
+}
@@ -0,0 +1,135 @@
+use macroquad::prelude::*;
+
+use crate::GridConfig;
+
+const MAX_ZOOM: f32 = 20.0;
+const FIT_MARGIN: f32 = 20.0;
+const KEEP_VISIBLE: f32 = 64.0; // Pixels of the maze that always stay on screen
+const DRAG_THRESHOLD: f32 = 4.0; // Less than this is a click, not a pan
+
+// Zoom and pan for the maze view, everything that draws or picks cells goes through view()
+pub struct MazeCamera
+{
+    pub zoom: f32,
+    pub pan: Vec2, // Screen pixels on top of grid_config.offset
+    pan_last: Option<Vec2>,
+    dragged: f32,
+}
+
+impl MazeCamera
+{
+    pub fn new() -> Self
+    {
+        MazeCamera { zoom: 1.0, pan: vec2(0.0, 0.0), pan_last: None, dragged: 0.0 }
+    }
+
+    // The grid config as it's currently seen, same grid, different cell size and offset
+    pub fn view(&self, grid_config: &GridConfig) -> GridConfig
+    {
+        GridConfig::new(grid_config.width, grid_config.height, grid_config.grid_width, grid_config.grid_height,
+            grid_config.cell_size * self.zoom, (grid_config.offset.0 + self.pan.x, grid_config.offset.1 + self.pan.y))
+    }
+
+    // Mouse wheel zooms around the mouse, middle drag pans, the keys come in through the UI
+    pub fn update(&mut self, grid_config: &GridConfig)
+    {
+        let mouse = Vec2::from(mouse_position());
+
+        let scroll = mouse_wheel().1;
+        if scroll != 0.0
+        {
+            let zoom = (self.zoom * 1.1_f32.powf(scroll.signum())).clamp(self.min_zoom(grid_config), MAX_ZOOM);
+            self.zoom_at(zoom, mouse, grid_config);
+        }
+
+        if is_mouse_button_pressed(MouseButton::Middle) { self.dragged = 0.0; }
+
+        if is_mouse_button_down(MouseButton::Middle)
+        {
+            if let Some(last) = self.pan_last
+            {
+                self.pan += mouse - last;
+                self.dragged += (mouse - last).length();
+            }
+            self.pan_last = Some(mouse);
+        }
+        else
+        {
+            self.pan_last = None;
+        }
+
+        self.clamp(grid_config);
+    }
+
+    // True once the middle button moved far enough to count as a pan instead of a click
+    pub fn dragged(&self) -> bool
+    {
+        self.dragged >= DRAG_THRESHOLD
+    }
+
+    // Keeps the cell under the point where it is
+    fn zoom_at(&mut self, zoom: f32, point: Vec2, grid_config: &GridConfig)
+    {
+        let base = vec2(grid_config.offset.0, grid_config.offset.1);
+        let cell = (point - base - self.pan) / (grid_config.cell_size * self.zoom);
+
+        self.zoom = zoom;
+        self.pan = point - base - cell * grid_config.cell_size * self.zoom;
+    }
+
+    // Whole maze centered in the window
+    pub fn fit(&mut self, grid_config: &GridConfig)
+    {
+        let (width, height) = (grid_config.grid_width as f32 * grid_config.cell_size, grid_config.grid_height as f32 * grid_config.cell_size);
+        let (screen_w, screen_h) = (screen_width() - FIT_MARGIN * 2.0, screen_height() - FIT_MARGIN * 2.0);
+
+        self.zoom = (screen_w / width).min(screen_h / height).clamp(f32::MIN_POSITIVE, MAX_ZOOM);
+
+        let size = vec2(width, height) * self.zoom;
+        let corner = (vec2(screen_width(), screen_height()) - size) * 0.5;
+        self.pan = corner - vec2(grid_config.offset.0, grid_config.offset.1);
+    }
+
+    // Cells at their laid out size, around whatever is in the middle of the window
+    pub fn actual_size(&mut self, grid_config: &GridConfig)
+    {
+        self.zoom_at(1.0, vec2(screen_width(), screen_height()) * 0.5, grid_config);
+        self.clamp(grid_config);
+    }
+
+    pub fn reset(&mut self, grid_config: &GridConfig)
+    {
+        self.zoom = 1.0;
+        self.pan = vec2(0.0, 0.0);
+        self.clamp(grid_config);
+    }
+
+    // Half of what fit would need, so big mazes can always be seen whole
+    fn min_zoom(&self, grid_config: &GridConfig) -> f32
+    {
+        let (width, height) = (grid_config.grid_width as f32 * grid_config.cell_size, grid_config.grid_height as f32 * grid_config.cell_size);
+        let fit = (screen_width() / width).min(screen_height() / height);
+
+        (fit * 0.5).min(0.1)
+    }
+
+    // Some of the maze stays on screen, so it can't get lost
+    fn clamp(&mut self, grid_config: &GridConfig)
+    {
+        let size = vec2(grid_config.grid_width as f32, grid_config.grid_height as f32) * grid_config.cell_size * self.zoom;
+        let screen = vec2(screen_width(), screen_height());
+        let base = vec2(grid_config.offset.0, grid_config.offset.1);
+
+        let keep = size.min(vec2(KEEP_VISIBLE, KEEP_VISIBLE));
+        let corner = (base + self.pan).max(keep - size).min(screen - keep);
+        self.pan = corner - base;
+    }
+}
+
+impl Default for MazeCamera
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
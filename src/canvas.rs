@@ -1,70 +1,349 @@
 use macroquad::prelude::*;
 
-use crate::{GridConfig, ui::FillMode};
+use crate::{GridConfig, brush::{self, BrushTip, Stabilizer, Stroke}, fill, generator, history::{self, History}, image, layers::{self, Layer, LayerKind, LayerSettings}, maze::{Dir, neighbour}, shapes::{self, ShapeTool}, symmetry::Symmetry, ui::FillMode};
+
+pub struct BrushSettings
+{
+    pub size: f32,
+    pub smoothing: f32,
+    pub color: Color,
+    pub eraser: bool,
+    pub fill_tolerance: f32, // 0 only fills the exact color, 1 everything
+    pub fill_diagonal: bool,
+    pub tip: BrushTip,
+    pub stabilizer: Stabilizer,
+    pub speed_thinning: f32, // 0 keeps the width, 1 halves it at a fast stroke
+    pub taper: f32, // Canvas pixels the stroke ends narrow over, 0 for none
+}
+
+pub const ERASE_COLOR: Color = Color::new(0.0, 0.0, 0.0, 0.0); // The empty canvas, means nothing for generation and lets lower layers show
+
+// Path pen overlay, ends and branches the way generation sees them
+const PEN_PATH_COLOR: Color = Color::new(0.55, 0.4, 0.7, 0.45);
+const PEN_END_COLOR: Color = Color::new(0.4, 0.85, 0.4, 0.8);
+const PEN_BRANCH_COLOR: Color = Color::new(0.9, 0.35, 0.3, 0.8);
+const PEN_IGNORED_COLOR: Color = Color::new(0.55, 0.55, 0.55, 0.25); // Paths besides the main one, generation only carves them
+
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 20.0;
+const FIT_MARGIN: f32 = 20.0;
+const KEEP_VISIBLE: f32 = 64.0; // Pixels of the canvas that always stay on screen
+const MIN_GRID_SPACING: f32 = 6.0; // Closer grid lines get thinned out
+const MINIMAP_SIZE: f32 = 160.0;
+const MINIMAP_MARGIN: f32 = 12.0;
+
+impl Default for BrushSettings
+{
+    fn default() -> Self
+    {
+        BrushSettings
+        {
+            size: 6.0,
+            smoothing: 0.5,
+            color: WHITE,
+            eraser: false,
+            fill_tolerance: 0.1,
+            fill_diagonal: false,
+            tip: BrushTip::Hard,
+            stabilizer: Stabilizer::Smooth,
+            speed_thinning: 0.0,
+            taper: 0.0,
+        }
+    }
+}
 
 pub struct Canvas
 {
-    canvas: Image,
-    pub texture: Texture2D,
+    layers: Vec<Layer>,
+    order: Vec<usize>, // Indices into layers, bottom to top, so history can keep using indices
+    active: usize,
     last_pos: Option<Vec2>,
     smooth_pos: Vec2,
+    stroke: Option<Stroke>, // Freehand only
     show_grid: bool,
     zoom: f32,
     offset: Vec2,
     pan_last: Option<Vec2>,
     grid_fill: bool,
     normal_fill: bool,
+    cell_flood: bool,
+    stroke_before: Option<(usize, Vec<u8>)>, // Layer and its bytes when the stroke started
+    tool: ShapeTool,
+    shape_points: Vec<Vec2>, // Placed so far, in canvas pixels
+    shape_cursor: Option<Vec2>, // Where the next point would go, for the preview
+    snap_to_cells: bool,
+    path_pen: bool,
+    pen_cells: Vec<Option<(usize, usize)>>, // Last painted cell of the current stroke, one per symmetric copy
+    path_mask: Vec<bool>, // What generation would read as path, only kept up to date for the path pen
+    path_mask_dirty: bool,
+    symmetry: Symmetry,
 }
 
 impl Canvas 
 {
     pub fn new(width: u16, height: u16) -> Self 
     {
-        let canvas = Image::gen_image_color(width, height, BLACK);
-        let texture = Texture2D::from_image(&canvas);
-        texture.set_filter(FilterMode::Nearest);
+        let layers = layers::default_layers(width, height);
+        let active = layers.iter().position(|layer| layer.kind == LayerKind::Path).unwrap_or(0);
 
         Self 
         {
-            canvas,
-            texture,
+            order: (0..layers.len()).collect(),
+            layers,
+            active,
             last_pos: None,
             smooth_pos: vec2(0.0, 0.0),
+            stroke: None,
             show_grid: false,
             zoom: 1.0,
             offset: vec2(0.0, 0.0),
             pan_last: None,
             grid_fill: false,
-            normal_fill: false
+            normal_fill: false,
+            cell_flood: false,
+            stroke_before: None,
+            tool: ShapeTool::Freehand,
+            shape_points: Vec::new(),
+            shape_cursor: None,
+            snap_to_cells: false,
+            path_pen: false,
+            pen_cells: Vec::new(),
+            path_mask: Vec::new(),
+            path_mask_dirty: true,
+            symmetry: Symmetry::None,
         }
     }
 
-    pub fn set_image(&mut self, image: Image)
+    // Scaled to the canvas if it came in at another size (loaded images)
+    pub fn set_layer_image(&mut self, layer: usize, image: Image)
     {
-        self.canvas = image;
-        self.texture = Texture2D::from_image(&self.canvas);
-        self.texture.set_filter(FilterMode::Nearest);
+        let size = self.get_size();
+        let layer = &mut self.layers[layer];
+
+        layer.image = if (image.width as f32, image.height as f32) == (size.x, size.y) { image } else { image::fit_image(&image, size.x as u16, size.y as u16) };
+        layer.refresh();
         self.last_pos = None;
         self.smooth_pos = vec2(0.0, 0.0);
+        self.path_mask_dirty = true;
+    }
+
+    // Like set_layer_image, with the edit to undo it
+    pub fn replace_layer(&mut self, layer: usize, image: Image) -> history::Edit
+    {
+        let before = self.layer_image(layer);
+        self.set_layer_image(layer, image);
+
+        history::Edit::CanvasImage { layer, before, after: self.layer_image(layer) }
+    }
+
+    pub fn layer_image(&self, layer: usize) -> Image
+    {
+        self.layers[layer].image.clone()
+    }
+
+    // What generation reads, the layers that feed it flattened
+    pub fn generation_image(&self) -> Image
+    {
+        layers::flatten(&self.layers, &self.order)
     }
 
-    pub fn get_image(&self) -> Image
+    pub fn layers(&self) -> &[Layer]
     {
-        self.canvas.clone()
+        &self.layers
     }
 
-    pub fn draw(&self, grid_config: &GridConfig)
+    // Bottom to top
+    pub fn layer_order(&self) -> &[usize]
     {
-        draw_texture_ex(&self.texture, -self.offset.x * self.zoom, -self.offset.y * self.zoom, WHITE, DrawTextureParams { dest_size: Some(self.get_size()*self.zoom), ..Default::default() });
+        &self.order
+    }
+
+    pub fn active_layer(&self) -> usize
+    {
+        self.active
+    }
+
+    pub fn layer_of(&self, kind: LayerKind) -> usize
+    {
+        self.layers.iter().position(|layer| layer.kind == kind).unwrap_or(self.active)
+    }
+
+    pub fn select_layer(&mut self, layer: usize)
+    {
+        if layer < self.layers.len() { self.active = layer; }
+    }
+
+    pub fn set_layer_settings(&mut self, layer: usize, settings: LayerSettings)
+    {
+        self.layers[layer].settings = settings;
+        self.path_mask_dirty = true;
+    }
+
+    // One step up (drawn later) or down
+    pub fn move_layer(&mut self, layer: usize, up: bool)
+    {
+        let Some(pos) = self.order.iter().position(|&i| i == layer) else { return; };
+        let other = if up { pos + 1 } else { pos.wrapping_sub(1) };
+        if other >= self.order.len() { return; }
+
+        self.order.swap(pos, other);
+        self.path_mask_dirty = true;
+    }
+
+    fn image(&self) -> &Image
+    {
+        &self.layers[self.active].image
+    }
+
+    fn image_mut(&mut self) -> &mut Image
+    {
+        &mut self.layers[self.active].image
+    }
+
+    pub fn draw(&self, brush: &BrushSettings, grid_config: &GridConfig)
+    {
+        let (corner, size) = (self.canvas_to_screen(vec2(0.0, 0.0)), self.get_size() * self.zoom);
+
+        // Empty everywhere is black, like a single image used to be
+        draw_rectangle(corner.x, corner.y, size.x, size.y, BLACK);
+        for layer in self.order.iter().map(|&i| &self.layers[i]).filter(|layer| layer.settings.visible)
+        {
+            draw_texture_ex(&layer.texture, corner.x, corner.y, Color::new(1.0, 1.0, 1.0, layer.settings.opacity), DrawTextureParams { dest_size: Some(size), ..Default::default() });
+        }
 
         if self.show_grid
         {
             self.draw_grid(grid_config);
         }
+
+        if self.path_pen { self.draw_path_mask(grid_config); }
+
+        self.draw_guides();
+        self.draw_minimap();
+
+        self.draw_shape_preview(brush);
     }
 
-    pub fn update(&mut self, block_input: bool, brush_size: f32, smoothing: f32, color: Color, grid_config: &GridConfig) 
+    // Path cells, with the ends and branches of the main path marked, and whether it's usable
+    // Other paths are dimmed, generation picks the longest one like here
+    fn draw_path_mask(&self, grid_config: &GridConfig)
     {
+        if self.path_mask.len() != grid_config.grid_size { return; }
+
+        let (grid_width, grid_size) = (grid_config.grid_width, grid_config.grid_size);
+        let size = self.cell_size(grid_config) * self.zoom;
+        let (mut ends, mut branches) = (0, 0);
+
+        let main_path = generator::main_path(&self.path_mask, grid_width, grid_size).unwrap_or_default();
+        let mut on_main_path = vec![false; grid_size];
+        for &i in main_path.iter() { on_main_path[i] = true; }
+
+        for i in (0..grid_size).filter(|&i| self.path_mask[i])
+        {
+            let neighbours = [Dir::Up, Dir::Down, Dir::Left, Dir::Right].iter()
+            .filter(|dir| neighbour(i, dir, grid_width, grid_size).is_some_and(|n| self.path_mask[n]))
+            .count();
+
+            let color = match neighbours
+            {
+                _ if !on_main_path[i] => PEN_IGNORED_COLOR,
+                1 => { ends += 1; PEN_END_COLOR },
+                3.. => { branches += 1; PEN_BRANCH_COLOR },
+                _ => PEN_PATH_COLOR,
+            };
+
+            let corner = self.canvas_to_screen(vec2((i % grid_width) as f32, (i / grid_width) as f32) * self.cell_size(grid_config));
+            draw_rectangle(corner.x, corner.y, size.x, size.y, color);
+        }
+
+        let status = match generator::main_path_ends(&self.path_mask, grid_width, grid_size)
+        {
+            _ if main_path.is_empty() => "No path painted".to_owned(),
+            Some(_) => format!("{} ends, {} branches, main path ok", ends, branches),
+            None => format!("{} ends, {} branches, path ends get placed on the two farthest apart", ends, branches),
+        };
+        let corner = self.canvas_to_screen(vec2(0.0, 0.0));
+        draw_text(&status, corner.x + 8.0, corner.y + 24.0, 24.0, WHITE);
+    }
+
+    // Paints every cell between the last one and the one under the mouse, one step at a time,
+    // so the stroke stays 4-connected like the path search needs. Every symmetric copy walks on its own
+    fn pen_to(&mut self, mouse: Vec2, color: Color, grid_config: &GridConfig)
+    {
+        let targets = self.symmetric_cells(mouse, grid_config);
+        if self.pen_cells == targets { return; }
+
+        let starts = if self.pen_cells.len() == targets.len() { std::mem::take(&mut self.pen_cells) } else { targets.clone() };
+        for (start, target) in starts.into_iter().zip(targets.iter().copied())
+        {
+            let Some(target) = target else { continue; };
+            let mut cell = start.unwrap_or(target);
+            self.fill_cell(cell, color, grid_config);
+
+            while cell != target
+            {
+                let (dx, dy) = (target.0 as isize - cell.0 as isize, target.1 as isize - cell.1 as isize);
+                if dx.abs() >= dy.abs() { cell.0 = (cell.0 as isize + dx.signum()) as usize; }
+                else { cell.1 = (cell.1 as isize + dy.signum()) as usize; }
+
+                self.fill_cell(cell, color, grid_config);
+            }
+        }
+
+        self.pen_cells = targets;
+        self.layers[self.active].refresh();
+        self.path_mask_dirty = true;
+    }
+
+    fn draw_guides(&self)
+    {
+        let size = self.get_size();
+        let color = Color::new(0.3, 0.8, 1.0, 0.5);
+
+        for (a, b) in self.symmetry.guides(size * 0.5, size.length())
+        {
+            let (a, b) = (self.canvas_to_screen(a), self.canvas_to_screen(b));
+            draw_line(a.x, a.y, b.x, b.y, 1.0, color);
+        }
+    }
+
+    // Rubber band of the shape being placed, ending at the mouse
+    fn draw_shape_preview(&self, brush: &BrushSettings)
+    {
+        if self.shape_points.is_empty() { return; }
+        let Some(cursor) = self.shape_cursor else { return; };
+
+        let mut points = self.shape_points.clone();
+        points.push(cursor);
+
+        let color = if brush.eraser { Color::new(0.5, 0.5, 0.5, 0.6) } else { Color { a: 0.6, ..brush.color } };
+        let thickness = (brush.size * 2.0 * self.zoom).max(1.0);
+
+        // Every copy the brush would draw
+        let center = self.get_size() * 0.5;
+        let copies: Vec<Vec<Vec2>> = shapes::outline(self.tool, &points).into_iter().map(|point| self.symmetry.points(point, center)).collect();
+
+        for segment in copies.windows(2)
+        {
+            for (&a, &b) in segment[0].iter().zip(segment[1].iter())
+            {
+                let (a, b) = (self.canvas_to_screen(a), self.canvas_to_screen(b));
+                draw_line(a.x, a.y, b.x, b.y, thickness, color);
+            }
+        }
+    }
+
+    pub fn update(&mut self, block_input: bool, brush: &BrushSettings, grid_config: &GridConfig, history: &mut History) 
+    {
+        let color = if brush.eraser { ERASE_COLOR } else { brush.color };
+
+        // Also catches undo and redo, they don't go through here
+        if self.path_pen && self.path_mask_dirty
+        {
+            self.path_mask = image::get_input_grid(&self.generation_image(), grid_config).0.path;
+            self.path_mask_dirty = false;
+        }
+
         if block_input { return; }
 
         let mouse_screen = vec2(mouse_position().0, mouse_position().1);
@@ -75,7 +354,7 @@ impl Canvas
         {
             let zoom_factor = 1.1_f32.powf(scroll);
             let old_zoom = self.zoom;
-            self.zoom = (self.zoom * zoom_factor).clamp(0.1, 20.0);
+            self.zoom = (self.zoom * zoom_factor).clamp(MIN_ZOOM, MAX_ZOOM);
 
             let before = self.offset + mouse_screen / old_zoom;
             let after = self.offset + mouse_screen / self.zoom;
@@ -89,7 +368,7 @@ impl Canvas
                 let delta_screen = mouse_screen - last;
                 let delta_canvas = delta_screen / self.zoom;
 
-                self.offset -= delta_canvas; // no clamping yet, so can get lost
+                self.offset -= delta_canvas;
             }
 
             self.pan_last = Some(mouse_screen);
@@ -98,37 +377,329 @@ impl Canvas
         {
             self.pan_last = None;
         }
+        self.clamp_view();
+
+        if self.tool != ShapeTool::Freehand && !self.normal_fill && !self.grid_fill && !self.cell_flood && !self.path_pen
+        {
+            self.update_shape(mouse, brush, color, grid_config, history);
+            return;
+        }
         
         if is_mouse_button_down(MouseButton::Left) 
         {
+            let freehand = !self.path_pen && !self.normal_fill && !self.cell_flood && !self.grid_fill;
             if self.last_pos.is_none()
             {
                 self.smooth_pos = mouse;
+                self.stroke_before = Some((self.active, self.image().bytes.clone()));
+                self.pen_cells.clear();
+                self.stroke = freehand.then(|| Stroke::new(mouse));
             }
-            self.smooth_pos = self.smooth_pos.lerp(mouse, smoothing);
+            self.smooth_pos = match &mut self.stroke
+            {
+                Some(stroke) => stroke.follow(mouse, brush),
+                None => self.smooth_pos.lerp(mouse, brush.smoothing),
+            };
 
-            if let Some(last) = self.last_pos
+            if self.path_pen
+            {
+                self.pen_to(mouse, color, grid_config);
+            }
+            else if self.normal_fill || self.cell_flood
             {
-                if self.normal_fill { self.fill_normal(mouse, color); }
-                else if self.grid_fill { self.fill_grid_cell(mouse, color, grid_config); }
-                else { self.draw_line(last, self.smooth_pos, brush_size, color); }
+                // Once per click, holding the button doesn't fill again
+                if self.last_pos.is_none()
+                {
+                    // A copy landing in an area an earlier one already filled finds nothing left to do
+                    for point in self.symmetry.points(mouse, self.get_size() * 0.5)
+                    {
+                        if self.normal_fill { self.fill_normal(point, color, brush); }
+                        else { self.fill_cells(point, color, brush, grid_config); }
+                    }
 
-                self.texture.update(&self.canvas);
+                    self.layers[self.active].refresh();
+                    self.path_mask_dirty = true;
+                }
+            }
+            else if self.grid_fill
+            {
+                if self.last_pos.is_some()
+                {
+                    self.fill_grid_cell(mouse, color, grid_config);
+                    self.layers[self.active].refresh();
+                    self.path_mask_dirty = true;
+                }
+            }
+            else if let Some(stroke) = &mut self.stroke
+            {
+                let from = stroke.last(brush);
+                let width = stroke.add(self.smooth_pos, brush);
+                let (last, last_width) = from.unwrap_or((self.smooth_pos, width));
+
+                self.draw_line(last, self.smooth_pos, (last_width, width), color, brush.tip);
+                self.layers[self.active].refresh();
+                self.path_mask_dirty = true;
             }
             self.last_pos = Some(self.smooth_pos);
         }
         else
         {
             self.last_pos = None;
+
+            // Now that the end is known, the stroke gets drawn again with both ends tapered
+            if let Some(stroke) = self.stroke.take()
+            && brush.taper > 0.0
+            && let Some((layer, before)) = &self.stroke_before
+            {
+                let layer = *layer;
+                self.layers[layer].image.bytes.copy_from_slice(before);
+                let points = stroke.tapered(brush.taper);
+                if let [(pen, width)] = points[..] { self.draw_brush(pen, width, color, brush.tip); }
+                for segment in points.windows(2)
+                {
+                    self.draw_line(segment[0].0, segment[1].0, (segment[0].1, segment[1].1), color, brush.tip);
+                }
+                self.layers[layer].refresh();
+                self.path_mask_dirty = true;
+            }
+
+            // Stroke finished, only the changed area goes into the history
+            if let Some((layer, before)) = self.stroke_before.take()
+            && let Some(edit) = history::canvas_patch(layer, &before, &self.layers[layer].image)
+            {
+                history.push(edit);
+            }
+        }
+    }
+
+    // Lines, rectangles and ellipses are dragged, bezier and polyline points are clicked
+    fn update_shape(&mut self, mouse: Vec2, brush: &BrushSettings, color: Color, grid_config: &GridConfig, history: &mut History)
+    {
+        let mut point = if self.snap_to_cells { self.snap_to_cell(mouse, grid_config) } else { mouse };
+
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if shift && let Some(&last) = self.shape_points.last()
+        {
+            point = shapes::constrain(last, point);
+        }
+        self.shape_cursor = Some(point);
+
+        if is_key_pressed(KeyCode::Escape)
+        {
+            self.shape_points.clear();
+            return;
+        }
+
+        match self.tool
+        {
+            ShapeTool::Line | ShapeTool::Rectangle | ShapeTool::Ellipse =>
+            {
+                if is_mouse_button_pressed(MouseButton::Left) { self.shape_points = vec![point]; }
+
+                if is_mouse_button_released(MouseButton::Left) && self.shape_points.len() == 1
+                {
+                    self.shape_points.push(point);
+                    self.commit_shape(brush, color, history);
+                }
+            },
+            ShapeTool::Bezier =>
+            {
+                if is_mouse_button_pressed(MouseButton::Left)
+                {
+                    self.shape_points.push(point);
+                    if self.shape_points.len() == 3 { self.commit_shape(brush, color, history); }
+                }
+            },
+            ShapeTool::Polyline =>
+            {
+                if is_mouse_button_pressed(MouseButton::Left) { self.shape_points.push(point); }
+
+                if (is_mouse_button_pressed(MouseButton::Right) || is_key_pressed(KeyCode::Enter)) && self.shape_points.len() >= 2
+                {
+                    self.commit_shape(brush, color, history);
+                }
+            },
+            ShapeTool::Freehand => {},
+        }
+    }
+
+    // Rasterized into the image, as one history entry
+    fn commit_shape(&mut self, brush: &BrushSettings, color: Color, history: &mut History)
+    {
+        let before = self.image().bytes.clone();
+
+        for segment in shapes::outline(self.tool, &self.shape_points).windows(2)
+        {
+            self.draw_line(segment[0], segment[1], (brush.size, brush.size), color, brush.tip);
+        }
+        self.shape_points.clear();
+        self.layers[self.active].refresh();
+        self.path_mask_dirty = true;
+
+        if let Some(edit) = history::canvas_patch(self.active, &before, self.image())
+        {
+            history.push(edit);
         }
     }
 
+    // Whole cells in the path color, as one history entry
+    pub fn paint_path(&mut self, path: &[bool], grid_config: &GridConfig, history: &mut History)
+    {
+        let before = self.image().bytes.clone();
+        let grid_width = grid_config.grid_width;
+
+        for i in (0..path.len()).filter(|&i| path[i])
+        {
+            self.fill_cell((i % grid_width, i / grid_width), WHITE, grid_config);
+        }
+        self.layers[self.active].refresh();
+        self.path_mask_dirty = true;
+
+        if let Some(edit) = history::canvas_patch(self.active, &before, self.image())
+        {
+            history.push(edit);
+        }
+    }
+
+    pub fn set_tool(&mut self, tool: ShapeTool)
+    {
+        self.tool = tool;
+        self.shape_points.clear();
+    }
+
+    pub fn set_symmetry(&mut self, symmetry: Symmetry)
+    {
+        self.symmetry = symmetry;
+    }
+
+    pub fn set_snap_to_cells(&mut self, snap: bool)
+    {
+        self.snap_to_cells = snap;
+    }
+
+    // Size of one maze cell in canvas pixels
+    fn cell_size(&self, grid_config: &GridConfig) -> Vec2
+    {
+        let size = self.get_size();
+        vec2(size.x / grid_config.grid_width as f32, size.y / grid_config.grid_height as f32)
+    }
+
+    // Maze cell under a canvas point, points outside get the closest one
+    fn cell_at(&self, point: Vec2, grid_config: &GridConfig) -> (usize, usize)
+    {
+        let cell_size = self.cell_size(grid_config);
+
+        let gx = (point.x / cell_size.x).floor().clamp(0.0, (grid_config.grid_width-1) as f32) as usize;
+        let gy = (point.y / cell_size.y).floor().clamp(0.0, (grid_config.grid_height-1) as f32) as usize;
+
+        (gx, gy)
+    }
+
+    fn snap_to_cell(&self, point: Vec2, grid_config: &GridConfig) -> Vec2
+    {
+        let (gx, gy) = self.cell_at(point, grid_config);
+        (vec2(gx as f32, gy as f32) + 0.5) * self.cell_size(grid_config)
+    }
+
+    pub fn write_region(&mut self, layer: usize, x: u32, y: u32, width: u32, height: u32, bytes: &[u8])
+    {
+        let layer = &mut self.layers[layer];
+        let canvas_width = layer.image.width();
+        let row_len = width as usize * 4;
+
+        for row in 0..height as usize
+        {
+            let dst = ((y as usize + row) * canvas_width + x as usize) * 4;
+            let src = row * row_len;
+            layer.image.bytes[dst..dst + row_len].copy_from_slice(&bytes[src..src + row_len]);
+        }
+
+        layer.refresh();
+        self.path_mask_dirty = true;
+    }
+
+    // Keeps whatever was in the middle of the window in the middle, the image itself is untouched
+    pub fn resize_view(&mut self, old_screen: Vec2, new_screen: Vec2)
+    {
+        self.offset += (old_screen - new_screen) * 0.5 / self.zoom;
+        self.pan_last = None;
+        self.clamp_view();
+    }
+
+    // Whole canvas centered in the window
+    pub fn fit_view(&mut self)
+    {
+        let size = self.get_size();
+        let screen = vec2(screen_width(), screen_height());
+        let available = screen - FIT_MARGIN * 2.0;
+
+        self.zoom = (available.x / size.x).min(available.y / size.y).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset = -(screen - size * self.zoom) * 0.5 / self.zoom;
+        self.pan_last = None;
+    }
+
+    // One canvas pixel per screen pixel, around whatever is in the middle of the window
+    pub fn actual_size(&mut self)
+    {
+        let center = vec2(screen_width(), screen_height()) * 0.5;
+        let middle = self.screen_to_canvas(center);
+
+        self.zoom = 1.0;
+        self.offset = middle - center;
+        self.clamp_view();
+    }
+
+    // Back to how it starts, top left corner of the canvas at the top left of the window
+    pub fn reset_view(&mut self)
+    {
+        self.zoom = 1.0;
+        self.offset = vec2(0.0, 0.0);
+        self.pan_last = None;
+    }
+
+    // Some of the canvas stays on screen, so it can't get lost
+    fn clamp_view(&mut self)
+    {
+        let size = self.get_size() * self.zoom;
+        let screen = vec2(screen_width(), screen_height());
+
+        let keep = size.min(vec2(KEEP_VISIBLE, KEEP_VISIBLE));
+        let corner = self.canvas_to_screen(vec2(0.0, 0.0)).max(keep - size).min(screen - keep);
+        self.offset = -corner / self.zoom;
+    }
+
+    // Only while part of the canvas is off screen, the whole canvas with the visible part outlined
+    fn draw_minimap(&self)
+    {
+        let size = self.get_size();
+        let screen = vec2(screen_width(), screen_height());
+        let (top_left, bottom_right) = (self.screen_to_canvas(vec2(0.0, 0.0)), self.screen_to_canvas(screen));
+        if top_left.x <= 0.0 && top_left.y <= 0.0 && bottom_right.x >= size.x && bottom_right.y >= size.y { return; }
+
+        let scale = MINIMAP_SIZE / size.x.max(size.y);
+        let map_size = size * scale;
+        let corner = screen - map_size - MINIMAP_MARGIN;
+
+        draw_rectangle(corner.x - 2.0, corner.y - 2.0, map_size.x + 4.0, map_size.y + 4.0, Color::new(0.1, 0.1, 0.1, 0.85));
+        draw_rectangle(corner.x, corner.y, map_size.x, map_size.y, BLACK);
+        for layer in self.order.iter().map(|&i| &self.layers[i]).filter(|layer| layer.settings.visible)
+        {
+            draw_texture_ex(&layer.texture, corner.x, corner.y, Color::new(1.0, 1.0, 1.0, layer.settings.opacity), DrawTextureParams { dest_size: Some(map_size), ..Default::default() });
+        }
+
+        let view_min = corner + top_left.max(Vec2::ZERO) * scale;
+        let view_max = corner + bottom_right.min(size) * scale;
+        draw_rectangle_lines(view_min.x, view_min.y, view_max.x - view_min.x, view_max.y - view_min.y, 2.0, YELLOW);
+    }
+
     pub fn get_size(&self) -> Vec2
     {
-        vec2(self.canvas.width() as f32, self.canvas.height() as f32)
+        let image = &self.layers[0].image;
+        vec2(image.width() as f32, image.height() as f32)
     }
 
-    fn draw_line(&mut self, a: Vec2, b: Vec2, brush_size: f32, color: Color)
+    // Width goes from the first to the second size along the line
+    fn draw_line(&mut self, a: Vec2, b: Vec2, (from, to): (f32, f32), color: Color, tip: BrushTip)
     {
         let dist = a.distance(b);
         let steps = dist.max(1.0) as i32;
@@ -137,53 +708,46 @@ impl Canvas
         {
             let t = i as f32 / steps as f32;
             let p = a.lerp(b, t);
-            self.draw_brush(p, brush_size, color);
+            self.draw_brush(p, from + (to - from) * t, color, tip);
         }
     }
 
-    fn draw_brush(&mut self, pos: Vec2, brush_size: f32, color: Color)
+    // Once for every symmetric copy
+    fn draw_brush(&mut self, pos: Vec2, brush_size: f32, color: Color, tip: BrushTip)
     {
-        let r = brush_size as i32;
-
-        for y in -r..=r
+        let center = self.get_size() * 0.5;
+        for point in self.symmetry.points(pos, center)
         {
-            for x in -r..=r
-            {
-                if x * x + y * y <= r * r
-                {
-                    let px = pos.x as i32 + x;
-                    let py = pos.y as i32 + y;
-
-                    if px >= 0 && py >= 0 && px < self.canvas.width() as i32 && py < self.canvas.height() as i32
-                    {
-                        self.canvas.set_pixel(px as u32, py as u32, color);
-                    }
-                }
-            }
+            brush::dab(self.image_mut(), point, brush_size, color, tip);
         }
     }
 
     fn draw_grid(&self, grid_config: &GridConfig)
     {
-        let (width, height) = (self.canvas.width() as f32, self.canvas.height() as f32);//screen_size();
+        let Vec2 { x: width, y: height } = self.get_size();
         
         let cell_width = width / grid_config.grid_width as f32;
         let cell_height = height / grid_config.grid_height as f32;
 
         let color = Color::new(1.0, 1.0, 1.0, 0.25);
 
-        for x in 0..=grid_config.grid_width
+        // Every line while they're far enough apart, then every 2nd, 4th, ...
+        let spacing = cell_width.min(cell_height) * self.zoom;
+        let mut step = 1;
+        while spacing > 0.0 && spacing * (step as f32) < MIN_GRID_SPACING { step *= 2; }
+
+        for x in (0..=grid_config.grid_width).filter(|&x| x % step == 0 || x == grid_config.grid_width)
         {
             let x_canvas = x as f32 * cell_width;
             let x_screen = (x_canvas - self.offset.x) * self.zoom;
-            draw_line(x_screen, -self.offset.y * self.zoom, x_screen, (height as f32 - self.offset.y) * self.zoom, 1.0, color); // macroquads draw line
+            draw_line(x_screen, -self.offset.y * self.zoom, x_screen, (height - self.offset.y) * self.zoom, 1.0, color); // macroquads draw line
         }
 
-        for y in 0..=grid_config.grid_height
+        for y in (0..=grid_config.grid_height).filter(|&y| y % step == 0 || y == grid_config.grid_height)
         {
             let y_canvas = y as f32 * cell_height;
             let y_screen = (y_canvas - self.offset.y) * self.zoom;
-            draw_line(-self.offset.x * self.zoom, y_screen, (width as f32 - self.offset.x) * self.zoom, y_screen, 1.0, color);
+            draw_line(-self.offset.x * self.zoom, y_screen, (width - self.offset.x) * self.zoom, y_screen, 1.0, color);
         }
     }
 
@@ -192,6 +756,11 @@ impl Canvas
         self.offset + screen / self.zoom
     }
 
+    fn canvas_to_screen(&self, canvas: Vec2) -> Vec2
+    {
+        (canvas - self.offset) * self.zoom
+    }
+
     pub fn show_grid(&mut self, show: bool)
     {
         self.show_grid = show;
@@ -200,53 +769,89 @@ impl Canvas
 
     pub fn fill_grid_cell(&mut self, mouse: Vec2, color: Color, grid_config: &GridConfig)
     {
-        let cell_width = self.canvas.width() as f32 / grid_config.grid_width as f32;
-        let cell_height = self.canvas.height() as f32 / grid_config.grid_height as f32;
+        for cell in self.symmetric_cells(mouse, grid_config).into_iter().flatten()
+        {
+            self.fill_cell(cell, color, grid_config);
+        }
+    }
 
-        let gx = (mouse.x / cell_width).floor().clamp(0.0, (grid_config.grid_width-1) as f32) as usize;
-        let gy = (mouse.y / cell_height).floor().clamp(0.0, (grid_config.grid_height-1) as f32) as usize;
+    // The cell under the point, then the cells under its symmetric copies, None for copies off the canvas
+    // Copies of the cell's middle, so mirrored cells line up even when the point is right on a cell edge
+    fn symmetric_cells(&self, point: Vec2, grid_config: &GridConfig) -> Vec<Option<(usize, usize)>>
+    {
+        let size = self.get_size();
+        let first = self.snap_to_cell(point, grid_config);
 
-        let start_x = (gx as f32 * cell_width).floor() as u32;
-        let start_y = (gy as f32 * cell_height).floor() as u32;
+        self.symmetry.points(first, size * 0.5).into_iter().enumerate()
+        .map(|(i, copy)| (i == 0 || (copy.cmpge(Vec2::ZERO).all() && copy.cmplt(size).all())).then(|| self.cell_at(copy, grid_config)))
+        .collect()
+    }
 
-        let end_x = ((gx+1) as f32 * cell_width).ceil() as u32;
-        let end_y = ((gy+1) as f32 * cell_height).ceil() as u32;
+    fn fill_cell(&mut self, cell: (usize, usize), color: Color, grid_config: &GridConfig)
+    {
+        let (start_x, start_y, end_x, end_y) = self.cell_rect(cell, grid_config);
 
-        for y in start_y..end_y.min(self.canvas.height() as u32)
+        let image = self.image_mut();
+        for y in start_y..end_y
         {
-            for x in start_x..end_x.min(self.canvas.width() as u32)
+            for x in start_x..end_x
             {
-                self.canvas.set_pixel(x, y, color);
+                image.set_pixel(x as u32, y as u32, color);
             }
         }
     }
 
-    pub fn fill_normal(&mut self, start: Vec2, color: Color)
+    // Pixels of a cell as start and end x and y, end exclusive
+    fn cell_rect(&self, (gx, gy): (usize, usize), grid_config: &GridConfig) -> (usize, usize, usize, usize)
     {
-        let width = self.canvas.width();
-        let height = self.canvas.height();
+        let Vec2 { x: cell_width, y: cell_height } = self.cell_size(grid_config);
+        let image = self.image();
 
-        let mut stack = vec![(start.x as i32, start.y as i32)];
-        let target_color = self.canvas.get_pixel(start.x as u32, start.y as u32);
+        let start_x = (gx as f32 * cell_width).floor() as usize;
+        let start_y = (gy as f32 * cell_height).floor() as usize;
 
-        if target_color == color { return; }
+        let end_x = (((gx+1) as f32 * cell_width).ceil() as usize).min(image.width());
+        let end_y = (((gy+1) as f32 * cell_height).ceil() as usize).min(image.height());
 
-        while let Some((x, y)) = stack.pop()
-        {
-            if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 { continue; }
+        (start_x, start_y, end_x, end_y)
+    }
 
-            let current = self.canvas.get_pixel(x as u32, y as u32);
-            if current != target_color
-            {
-                continue;
-            }
+    // Everything connected to the point within the tolerance, None if it's outside or already that color
+    fn flood(&self, start: Vec2, color: Color, brush: &BrushSettings) -> Option<(Vec<bool>, [u8; 4])>
+    {
+        let image = self.image();
+        if start.x < 0.0 || start.y < 0.0 || start.x >= image.width() as f32 || start.y >= image.height() as f32 { return None; }
 
-            self.canvas.set_pixel(x as u32, y as u32, color);
+        let (x, y) = (start.x as usize, start.y as usize);
+        let i = (y * image.width() + x) * 4;
+        let target: [u8; 4] = image.bytes[i..i + 4].try_into().ok()?;
+        let color: [u8; 4] = color.into();
+        if target == color { return None; }
 
-            stack.push((x+1, y));
-            stack.push((x-1, y));
-            stack.push((x, y+1));
-            stack.push((x, y-1));
+        Some((fill::flood(image, (x, y), brush.fill_tolerance, brush.fill_diagonal), target))
+    }
+
+    pub fn fill_normal(&mut self, start: Vec2, color: Color, brush: &BrushSettings)
+    {
+        let Some((filled, target)) = self.flood(start, color, brush) else { return; };
+        fill::paint(self.image_mut(), &filled, target, brush.fill_tolerance, color);
+    }
+
+    // Same fill, but only whole cells that it mostly reached, so drawn outlines still stop it
+    pub fn fill_cells(&mut self, start: Vec2, color: Color, brush: &BrushSettings, grid_config: &GridConfig)
+    {
+        let Some((filled, _)) = self.flood(start, color, brush) else { return; };
+        let width = self.image().width();
+
+        for gy in 0..grid_config.grid_height
+        {
+            for gx in 0..grid_config.grid_width
+            {
+                let (start_x, start_y, end_x, end_y) = self.cell_rect((gx, gy), grid_config);
+                let reached = (start_y..end_y).map(|y| filled[y * width + start_x..y * width + end_x].iter().filter(|&&f| f).count()).sum::<usize>();
+
+                if reached > 0 && reached * 2 >= (end_x - start_x) * (end_y - start_y) { self.fill_cell((gx, gy), color, grid_config); }
+            }
         }
     }
 
@@ -254,9 +859,11 @@ impl Canvas
     {
         match fill_mode 
         {
-            FillMode::NormalFill => { self.normal_fill=true; self.grid_fill=false; },
-            FillMode::GridFill => { self.normal_fill=false; self.grid_fill=true; },
-            FillMode::None => { self.normal_fill=false; self.grid_fill=false; },
+            FillMode::NormalFill => { self.normal_fill=true; self.grid_fill=false; self.cell_flood=false; self.path_pen=false; },
+            FillMode::GridFill => { self.normal_fill=false; self.grid_fill=true; self.cell_flood=false; self.path_pen=false; },
+            FillMode::CellFlood => { self.normal_fill=false; self.grid_fill=false; self.cell_flood=true; self.path_pen=false; },
+            FillMode::PathPen => { self.normal_fill=false; self.grid_fill=false; self.cell_flood=false; self.path_pen=true; },
+            FillMode::None => { self.normal_fill=false; self.grid_fill=false; self.cell_flood=false; self.path_pen=false; },
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,183 @@
+use crate::{GridConfig, generator, maze::{Cell, Dir}, solver};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum EndsStrategy
+{
+    Manual,    // Mouse clicks only
+    Diameter,  // The two cells farthest apart
+    ImagePath, // Both ends of the path from the input image
+    Sides,     // Farthest pair between two chosen sides
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Side
+{
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct EndsSettings
+{
+    pub strategy: EndsStrategy,
+    pub start_side: Side,
+    pub end_side: Side,
+    pub open_border: bool, // Carve the entrance and exit into the outer wall
+}
+
+impl Default for EndsSettings
+{
+    fn default() -> Self
+    {
+        EndsSettings { strategy: EndsStrategy::Manual, start_side: Side::Left, end_side: Side::Right, open_border: true }
+    }
+}
+
+// None for Manual, or when the strategy has nothing to work with (no image path)
+pub fn choose_ends(settings: &EndsSettings, grid: &[Cell], path_input: Option<&[bool]>, grid_config: &GridConfig) -> Option<(usize, usize)>
+{
+    match settings.strategy
+    {
+        EndsStrategy::Manual => None,
+        EndsStrategy::Diameter =>
+        {
+            // A printed maze has its openings on the border, so only border cells count then
+            let all: Vec<usize> = (0..grid_config.grid_size).collect();
+            let candidates = if settings.open_border { border_cells(grid_config) } else { all };
+            Some(farthest_pair(&candidates, &candidates, grid, grid_config))
+        },
+        EndsStrategy::ImagePath =>
+        {
+            let path = path_input?;
+            if path.len() != grid_config.grid_size { return None; }
+
+            // The two farthest apart, for a plain line those are its ends
+            // Branching paths like text can have two dead ends on a side branch, so counting ends isn't enough
+            let main_path = generator::main_path(path, grid_config.grid_width, grid_config.grid_size)?;
+            Some(farthest_pair(&main_path, &main_path, grid, grid_config))
+        },
+        EndsStrategy::Sides =>
+        {
+            let from = side_cells(settings.start_side, grid_config);
+            let to = side_cells(settings.end_side, grid_config);
+            Some(farthest_pair(&from, &to, grid, grid_config))
+        },
+    }
+}
+
+// Double BFS: the cell in `to` farthest from somewhere in `from`, then the cell in `from` farthest from that
+// Exact for the diameter of a perfect maze, a good guess otherwise
+fn farthest_pair(from: &[usize], to: &[usize], grid: &[Cell], grid_config: &GridConfig) -> (usize, usize)
+{
+    let farthest = |source: usize, candidates: &[usize]| -> usize
+    {
+        let distances = solver::distances(source, grid, grid_config);
+        candidates.iter().copied().max_by_key(|&cell| distances[cell].map_or(0, |d| d + 1)).unwrap_or(source)
+    };
+
+    let end = farthest(from[from.len() / 2], to);
+    let start = farthest(end, from);
+    (start, end)
+}
+
+pub fn side_cells(side: Side, grid_config: &GridConfig) -> Vec<usize>
+{
+    let (w, h) = (grid_config.grid_width, grid_config.grid_height);
+    match side
+    {
+        Side::Top => (0..w).collect(),
+        Side::Bottom => ((h - 1) * w..h * w).collect(),
+        Side::Left => (0..h).map(|y| y * w).collect(),
+        Side::Right => (0..h).map(|y| y * w + w - 1).collect(),
+    }
+}
+
+fn border_cells(grid_config: &GridConfig) -> Vec<usize>
+{
+    (0..grid_config.grid_size).filter(|&cell| outward_dir(cell, None, grid_config).is_some()).collect()
+}
+
+// Which outer wall to open for a border cell, the preferred side if the cell is on it
+pub fn outward_dir(cell: usize, preferred: Option<Side>, grid_config: &GridConfig) -> Option<Dir>
+{
+    let (x, y) = (cell % grid_config.grid_width, cell / grid_config.grid_width);
+    let sides =
+    [
+        (Side::Top, Dir::Up, y == 0),
+        (Side::Bottom, Dir::Down, y + 1 == grid_config.grid_height),
+        (Side::Left, Dir::Left, x == 0),
+        (Side::Right, Dir::Right, x + 1 == grid_config.grid_width),
+    ];
+
+    sides.iter().find(|(side, _, on_side)| *on_side && Some(*side) == preferred)
+    .or(sides.iter().find(|(_, _, on_side)| *on_side))
+    .map(|(_, dir, _)| *dir)
+}
+
+// Closes the whole border, then opens it again at start and end
+// Returns the ends actually used, interior ones (from an image path) get moved out to the border
+pub fn carve_openings(grid: &mut [Cell], start: usize, end: usize, settings: &EndsSettings, grid_config: &GridConfig) -> (usize, usize)
+{
+    for side in [Side::Top, Side::Bottom, Side::Left, Side::Right]
+    {
+        let dir = match side { Side::Top => Dir::Up, Side::Bottom => Dir::Down, Side::Left => Dir::Left, Side::Right => Dir::Right };
+        for cell in side_cells(side, grid_config) { grid[cell].set_wall(&dir, true); }
+    }
+
+    if !settings.open_border { return (start, end); }
+
+    let Some((start, end)) = nearest_border(start, end, grid, grid_config).zip(nearest_border(end, start, grid, grid_config)) else
+    {
+        println!("Error\nNo border cell reachable from the ends, the maze stays closed");
+        return (start, end);
+    };
+
+    let sides = if settings.strategy == EndsStrategy::Sides { (Some(settings.start_side), Some(settings.end_side)) } else { (None, None) };
+    if let Some(dir) = outward_dir(start, sides.0, grid_config) { grid[start].set_wall(&dir, false); }
+    if let Some(dir) = outward_dir(end, sides.1, grid_config) { grid[end].set_wall(&dir, false); }
+    (start, end)
+}
+
+// The closest border cell behind an end, seen from the other end, so the old solution stays part of the new one
+// Any closest border cell if nothing is behind it
+fn nearest_border(cell: usize, other: usize, grid: &[Cell], grid_config: &GridConfig) -> Option<usize>
+{
+    if outward_dir(cell, None, grid_config).is_some() { return Some(cell); }
+
+    let from_cell = solver::distances(cell, grid, grid_config);
+    let from_other = solver::distances(other, grid, grid_config);
+    let behind = |border: usize| matches!((from_other[border], from_other[cell], from_cell[border]), (Some(a), Some(b), Some(c)) if a == b + c);
+    let reachable: Vec<usize> = border_cells(grid_config).into_iter().filter(|&border| from_cell[border].is_some()).collect();
+
+    reachable.iter().copied().filter(|&border| behind(border)).min_by_key(|&border| from_cell[border])
+    .or(reachable.iter().copied().min_by_key(|&border| from_cell[border]))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::maze::{neighbour, opposite};
+
+    // 0 1 2
+    // 3 4 5    start 0, end 4 in the middle, 5 is the way out behind it
+    #[test]
+    fn interior_end_moves_to_the_border()
+    {
+        let grid_config = GridConfig::new(0.0, 0.0, 3, 3, 1.0, (0.0, 0.0));
+        let mut grid = vec![Cell::new(); grid_config.grid_size];
+        for (cell, dir) in [(0, Dir::Right), (1, Dir::Right), (0, Dir::Down), (3, Dir::Right), (4, Dir::Right)]
+        {
+            let other = neighbour(cell, &dir, grid_config.grid_width, grid_config.grid_size).unwrap();
+            grid[cell].set_wall(&dir, false);
+            grid[other].set_wall(&opposite(&dir), false);
+        }
+
+        let settings = EndsSettings { strategy: EndsStrategy::ImagePath, ..EndsSettings::default() };
+        assert_eq!(carve_openings(&mut grid, 0, 4, &settings, &grid_config), (0, 5));
+        assert!(!grid[5].has_wall(&Dir::Right));
+        assert!(grid[3].has_wall(&Dir::Left));
+    }
+}
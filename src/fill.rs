@@ -0,0 +1,167 @@
+use macroquad::prelude::*;
+
+// Biggest difference of any channel, alpha included
+pub fn difference(a: &[u8], b: &[u8]) -> u8
+{
+    a.iter().zip(b.iter()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0)
+}
+
+// Pixels connected to start that are within tolerance (0 to 1) of its color, found a row span at a time
+// Diagonal also spreads across corners
+pub fn flood(image: &Image, start: (usize, usize), tolerance: f32, diagonal: bool) -> Vec<bool>
+{
+    let (width, height) = (image.width(), image.height());
+    let mut filled = vec![false; width * height];
+    if start.0 >= width || start.1 >= height { return filled; }
+
+    let bytes = &image.bytes;
+    let start_idx = (start.1 * width + start.0) * 4;
+    let target = &bytes[start_idx..start_idx + 4];
+    let limit = (tolerance.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let matches = |i: usize| difference(&bytes[i * 4..i * 4 + 4], target) <= limit;
+
+    let mut stack = vec![start];
+    while let Some((x, y)) = stack.pop()
+    {
+        let row = y * width;
+        if filled[row + x] { continue; }
+
+        let mut left = x;
+        while left > 0 && !filled[row + left - 1] && matches(row + left - 1) { left -= 1; }
+        let mut right = x;
+        while right + 1 < width && !filled[row + right + 1] && matches(row + right + 1) { right += 1; }
+
+        filled[row + left..=row + right].fill(true);
+
+        // One seed per run of open pixels in the rows above and below
+        let (from, to) = if diagonal { (left.saturating_sub(1), (right + 1).min(width - 1)) } else { (left, right) };
+        for next_y in [y.wrapping_sub(1), y + 1]
+        {
+            if next_y >= height { continue; }
+
+            let next_row = next_y * width;
+            let mut in_run = false;
+            for next_x in from..=to
+            {
+                let open = !filled[next_row + next_x] && matches(next_row + next_x);
+                if open && !in_run { stack.push((next_x, next_y)); }
+                in_run = open;
+            }
+        }
+    }
+
+    filled
+}
+
+// Colors the filled pixels, the ones right outside that were at most twice the tolerance off get blended in,
+// so anti-aliased outlines don't leave a ring of the old color
+pub fn paint(image: &mut Image, filled: &[bool], target: [u8; 4], tolerance: f32, color: Color)
+{
+    let width = image.width();
+    let color: [u8; 4] = color.into();
+    let limit = tolerance.clamp(0.0, 1.0) * 255.0;
+
+    // Without tolerance only exact matches get filled, there's nothing in between to blend
+    let edges: Vec<usize> = if limit <= 0.0 { Vec::new() } else { (0..filled.len()).filter(|&i|
+    {
+        let (x, y) = (i % width, i / width);
+        !filled[i] && ((x > 0 && filled[i - 1]) || (x + 1 < width && filled[i + 1]) || (y > 0 && filled[i - width]) || filled.get(i + width) == Some(&true))
+    }).collect() };
+
+    for i in edges
+    {
+        let pixel = &mut image.bytes[i * 4..i * 4 + 4];
+        let closeness = ((limit * 2.0 - difference(pixel, &target) as f32) / limit).clamp(0.0, 1.0);
+        if closeness == 0.0 { continue; }
+
+        for (channel, &to) in pixel.iter_mut().zip(color.iter())
+        {
+            *channel = (*channel as f32 + (to as f32 - *channel as f32) * closeness).round() as u8;
+        }
+    }
+
+    for (pixel, _) in image.bytes.chunks_exact_mut(4).zip(filled.iter()).filter(|(_, filled)| **filled)
+    {
+        pixel.copy_from_slice(&color);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const CLEAR: Color = Color::new(0.0, 0.0, 0.0, 0.0);
+    const FILL: Color = Color::new(1.0, 0.0, 0.0, 1.0);
+
+    fn pixel(image: &Image, x: usize, y: usize) -> [u8; 4]
+    {
+        let i = (y * image.width() + x) * 4;
+        image.bytes[i..i + 4].try_into().unwrap()
+    }
+
+    // Two areas that only touch at a corner, split by a white diagonal
+    fn diagonal_split() -> Image
+    {
+        let mut image = Image::gen_image_color(8, 8, CLEAR);
+        for i in 0..8
+        {
+            image.set_pixel(i, i, WHITE);
+        }
+        image
+    }
+
+    #[test]
+    fn corners_only_connect_with_diagonal()
+    {
+        let image = diagonal_split();
+
+        let straight = flood(&image, (7, 0), 0.0, false);
+        assert!(!straight[7 * 8], "spread across the diagonal");
+        assert_eq!(straight.iter().filter(|&&filled| filled).count(), 28);
+
+        let diagonal = flood(&image, (7, 0), 0.0, true);
+        assert!(diagonal[7 * 8], "didn't spread across the corners");
+        assert_eq!(diagonal.iter().filter(|&&filled| filled).count(), 56);
+    }
+
+    #[test]
+    fn tolerance_limits()
+    {
+        let mut image = Image::gen_image_color(4, 1, Color::from_rgba(100, 100, 100, 255));
+        image.set_pixel(1, 0, Color::from_rgba(101, 100, 100, 255));
+        image.set_pixel(2, 0, Color::from_rgba(200, 100, 100, 255));
+        image.set_pixel(3, 0, Color::from_rgba(100, 100, 100, 255));
+
+        // Zero only takes the exact color, so even one step off stops it
+        assert_eq!(flood(&image, (0, 0), 0.0, false), [true, false, false, false]);
+        assert_eq!(flood(&image, (0, 0), 0.01, false), [true, true, false, false]);
+        assert_eq!(flood(&image, (0, 0), 1.0, false), [true, true, true, true]);
+
+        // And paints nothing but the filled pixels
+        let (filled, target) = (flood(&image, (0, 0), 0.0, false), pixel(&image, 0, 0));
+        paint(&mut image, &filled, target, 0.0, FILL);
+        assert_eq!(pixel(&image, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 1, 0), [101, 100, 100, 255]);
+    }
+
+    // On a see-through layer the outline keeps its alpha, only pixels close to the filled color get blended
+    #[test]
+    fn edges_on_transparent_layer()
+    {
+        let mut image = Image::gen_image_color(5, 1, CLEAR);
+        image.set_pixel(1, 0, Color::from_rgba(0, 0, 0, 30)); // Faint anti-aliasing, within twice the tolerance
+        image.set_pixel(2, 0, WHITE);
+
+        let target = pixel(&image, 0, 0);
+        let filled = flood(&image, (0, 0), 0.1, false);
+        assert_eq!(filled, [true, false, false, false, false]);
+
+        paint(&mut image, &filled, target, 0.1, FILL);
+        assert_eq!(pixel(&image, 0, 0), [255, 0, 0, 255]);
+        let blended = pixel(&image, 1, 0);
+        assert!(blended[3] > 30 && blended[3] < 255, "edge alpha {} wasn't blended", blended[3]);
+        assert_eq!(pixel(&image, 2, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(&image, 3, 0), [0, 0, 0, 0]);
+    }
+}
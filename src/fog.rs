@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+
+use crate::{GridConfig, maze::{Cell, Dir, neighbour}};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum FogMode
+{
+    Radius,      // Everything reachable within radius steps
+    LineOfSight, // Straight lines that don't cross a wall, within radius
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct FogSettings
+{
+    pub enabled: bool,
+    pub mode: FogMode,
+    pub radius: usize,
+    pub reveal_image: bool,
+}
+
+impl Default for FogSettings
+{
+    fn default() -> Self
+    {
+        FogSettings { enabled: false, mode: FogMode::LineOfSight, radius: 4, reveal_image: true }
+    }
+}
+
+pub struct Fog
+{
+    pub settings: FogSettings,
+    pub visible: Vec<bool>,
+    pub seen: Vec<bool>, // Stays true once visible, drawn dimmed
+}
+
+impl Fog
+{
+    pub fn new(settings: FogSettings) -> Self
+    {
+        Fog { settings, visible: Vec::new(), seen: Vec::new() }
+    }
+
+    pub fn reset(&mut self, grid_size: usize)
+    {
+        self.visible = vec![false; grid_size];
+        self.seen = vec![false; grid_size];
+    }
+
+    // Only needs to run when the player moved
+    pub fn update(&mut self, player: usize, grid: &[Cell], grid_config: &GridConfig)
+    {
+        self.visible = match self.settings.mode
+        {
+            FogMode::Radius => visible_by_radius(player, self.settings.radius, grid, grid_config),
+            FogMode::LineOfSight => visible_by_sight(player, self.settings.radius, grid, grid_config),
+        };
+
+        for (seen, visible) in self.seen.iter_mut().zip(self.visible.iter())
+        {
+            *seen |= *visible;
+        }
+    }
+
+    pub fn is_visible(&self, cell: usize) -> bool
+    {
+        !self.settings.enabled || self.visible[cell]
+    }
+
+    pub fn is_seen(&self, cell: usize) -> bool
+    {
+        !self.settings.enabled || self.seen[cell]
+    }
+}
+
+// BFS through open walls
+pub fn visible_by_radius(player: usize, radius: usize, grid: &[Cell], grid_config: &GridConfig) -> Vec<bool>
+{
+    let mut visible = vec![false; grid_config.grid_size];
+    let mut queue = VecDeque::new();
+
+    visible[player] = true;
+    queue.push_back((player, 0));
+
+    while let Some((cell, distance)) = queue.pop_front()
+    {
+        if distance == radius { continue; }
+
+        for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+        {
+            if grid[cell].has_wall(&dir) { continue; }
+
+            if let Some(next) = neighbour(cell, &dir, grid_config.grid_width, grid_config.grid_size)
+            && !visible[next]
+            {
+                visible[next] = true;
+                queue.push_back((next, distance + 1));
+            }
+        }
+    }
+
+    visible
+}
+
+// Every cell in range whose center can be seen from the player's center
+pub fn visible_by_sight(player: usize, radius: usize, grid: &[Cell], grid_config: &GridConfig) -> Vec<bool>
+{
+    let grid_width = grid_config.grid_width as isize;
+    let grid_height = grid_config.grid_height as isize;
+    let mut visible = vec![false; grid_config.grid_size];
+
+    let px = player as isize % grid_width;
+    let py = player as isize / grid_width;
+    let r = radius as isize;
+
+    for y in (py - r).max(0)..=(py + r).min(grid_height - 1)
+    {
+        for x in (px - r).max(0)..=(px + r).min(grid_width - 1)
+        {
+            if (x - px).pow(2) + (y - py).pow(2) > r * r { continue; }
+
+            if ray_is_open((px, py), (x, y), grid, grid_config)
+            {
+                visible[(y * grid_width + x) as usize] = true;
+            }
+        }
+    }
+
+    visible
+}
+
+// Walks the cells a line between two cell centers passes through, checking the walls it crosses
+// When the line goes exactly through a corner, either way around is fine
+fn ray_is_open(from: (isize, isize), to: (isize, isize), grid: &[Cell], grid_config: &GridConfig) -> bool
+{
+    let grid_width = grid_config.grid_width;
+    let grid_size = grid_config.grid_size;
+
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let step_x = if dx > 0 { Dir::Right } else { Dir::Left };
+    let step_y = if dy > 0 { Dir::Down } else { Dir::Up };
+    let (nx, ny) = (dx.abs(), dy.abs());
+
+    let try_step = |cell: usize, dir: &Dir| -> Option<usize>
+    {
+        if grid[cell].has_wall(dir) { return None; }
+        neighbour(cell, dir, grid_width, grid_size)
+    };
+
+    let mut cell = (from.1 as usize) * grid_width + from.0 as usize;
+    let (mut ix, mut iy) = (0, 0);
+
+    // Compares where the next vertical and horizontal boundary crossings are along the line
+    while ix < nx || iy < ny
+    {
+        let decision = (1 + 2 * ix) * ny - (1 + 2 * iy) * nx;
+
+        if decision == 0
+        {
+            let via_x = try_step(cell, &step_x).and_then(|c| try_step(c, &step_y));
+            let via_y = try_step(cell, &step_y).and_then(|c| try_step(c, &step_x));
+            match via_x.or(via_y)
+            {
+                Some(next) => cell = next,
+                None => return false,
+            }
+            ix += 1;
+            iy += 1;
+        }
+        else if decision < 0
+        {
+            match try_step(cell, &step_x)
+            {
+                Some(next) => cell = next,
+                None => return false,
+            }
+            ix += 1;
+        }
+        else
+        {
+            match try_step(cell, &step_y)
+            {
+                Some(next) => cell = next,
+                None => return false,
+            }
+            iy += 1;
+        }
+    }
+
+    true
+}
@@ -0,0 +1,302 @@
+use std::{fs, path::PathBuf, time::{Duration, Instant}};
+
+use macroquad::prelude::*;
+
+use crate::{GridConfig, canvas::Canvas, fog::{Fog, FogSettings}, maze::{Dir, Maze, neighbour}};
+
+const SCORE_FILE: &str = "scores.txt"; // Next to the executable, not wherever it was started from
+const MAX_SCORES: usize = 10;
+const REPEAT_DELAY: f32 = 0.2; // Holding a key starts repeating after this
+const REPEAT_RATE: f32 = 0.06;
+
+const PLAYER_COLOR: Color = Color::new(0.95, 0.75, 0.2, 1.0);
+const TRAIL_COLOR: Color = Color::new(0.3, 0.45, 0.6, 1.0);
+const REVEALED_TRAIL_COLOR: Color = Color::new(0.3, 0.45, 0.6, 0.25); // See-through, the trail covers exactly the revealed cells
+const SEEN_WALL_COLOR: Color = Color::new(0.45, 0.45, 0.45, 1.0);
+const SEEN_IMAGE_TINT: Color = Color::new(0.45, 0.45, 0.45, 1.0);
+
+#[derive(Clone)]
+pub struct Score
+{
+    pub time: Duration,
+    pub moves: usize,
+}
+
+// Walking through the maze yourself
+pub struct Game
+{
+    pub player: usize,
+    pub trail: Vec<bool>,
+    pub moves: usize,
+    started: Option<Instant>,
+    pub finished: Option<Duration>,
+    key: String, // Which maze the scores belong to
+    pub scores: Vec<Score>,
+    held: Option<(Dir, f32)>, // Key repeat
+    pub fog: Fog,
+    pub held_keys: u32, // Bit per puzzle key
+    pub picked_up: Vec<bool>,
+    pub collected: usize,
+}
+
+impl Game
+{
+    pub fn new(maze: &Maze, grid_config: &GridConfig) -> Self
+    {
+        let mut game = Game
+        {
+            player: maze.start,
+            trail: Vec::new(),
+            moves: 0,
+            started: None,
+            finished: None,
+            key: String::new(),
+            scores: Vec::new(),
+            held: None,
+            fog: Fog::new(FogSettings::default()),
+            held_keys: 0,
+            picked_up: Vec::new(),
+            collected: 0,
+        };
+        game.reset(maze, grid_config);
+        game
+    }
+
+    pub fn reset(&mut self, maze: &Maze, grid_config: &GridConfig)
+    {
+        self.player = maze.start;
+        self.trail = vec![false; grid_config.grid_size];
+        self.trail[maze.start] = true;
+        self.moves = 0;
+        self.started = None;
+        self.finished = None;
+        self.held = None;
+        self.key = format!("{}x{}-{:016x}", grid_config.grid_width, grid_config.grid_height, maze.fingerprint());
+        self.scores = load_scores(&self.key);
+
+        self.held_keys = 0;
+        self.picked_up = vec![false; grid_config.grid_size];
+        self.collected = 0;
+        self.pick_up(maze);
+
+        self.fog.reset(grid_config.grid_size);
+        self.fog.update(self.player, &maze.grid, grid_config);
+    }
+
+    pub fn set_fog(&mut self, settings: FogSettings, maze: &Maze, grid_config: &GridConfig)
+    {
+        self.fog.settings = settings;
+        self.fog.update(self.player, &maze.grid, grid_config);
+    }
+
+    pub fn elapsed(&self) -> Duration
+    {
+        self.finished.unwrap_or(self.started.map_or(Duration::ZERO, |started| started.elapsed()))
+    }
+
+    pub fn update(&mut self, maze: &Maze, grid_config: &GridConfig)
+    {
+        if self.finished.is_some() { return; }
+
+        let Some(dir) = self.input_direction() else { return; };
+
+        if maze.grid[self.player].has_wall(&dir) { return; }
+        let Some(next) = neighbour(self.player, &dir, grid_config.grid_width, grid_config.grid_size) else { return; };
+
+        if let Some(door) = maze.puzzle.door_between(self.player, next)
+        && self.held_keys & (1 << door.key) == 0
+        {
+            return;
+        }
+
+        if self.started.is_none() { self.started = Some(Instant::now()); }
+
+        self.player = next;
+        self.trail[next] = true;
+        self.moves += 1;
+        self.pick_up(maze);
+        self.fog.update(self.player, &maze.grid, grid_config);
+
+        if self.player == maze.end
+        {
+            let time = self.elapsed();
+            self.finished = Some(time);
+            self.scores.push(Score { time, moves: self.moves });
+            self.scores.sort_by_key(|score| (score.time, score.moves));
+            self.scores.truncate(MAX_SCORES);
+            save_scores(&self.key, &self.scores);
+        }
+    }
+
+    fn pick_up(&mut self, maze: &Maze)
+    {
+        if self.picked_up[self.player] { return; }
+
+        for key in maze.puzzle.keys.iter().filter(|key| key.cell == self.player)
+        {
+            self.held_keys |= 1 << key.id;
+            self.picked_up[self.player] = true;
+        }
+        if maze.puzzle.collectibles.contains(&self.player)
+        {
+            self.collected += 1;
+            self.picked_up[self.player] = true;
+        }
+    }
+
+    // Arrow keys or WASD, with key repeat while held
+    fn input_direction(&mut self) -> Option<Dir>
+    {
+        let keys =
+        [
+            (Dir::Up, KeyCode::Up, KeyCode::W),
+            (Dir::Down, KeyCode::Down, KeyCode::S),
+            (Dir::Left, KeyCode::Left, KeyCode::A),
+            (Dir::Right, KeyCode::Right, KeyCode::D),
+        ];
+
+        for (dir, arrow, letter) in keys
+        {
+            if is_key_pressed(arrow) || is_key_pressed(letter)
+            {
+                self.held = Some((dir, REPEAT_DELAY));
+                return Some(dir);
+            }
+        }
+
+        let (dir, timer) = self.held?;
+        let (_, arrow, letter) = keys.into_iter().find(|(d, _, _)| *d == dir)?;
+        if !is_key_down(arrow) && !is_key_down(letter)
+        {
+            self.held = None;
+            return None;
+        }
+
+        let timer = timer - get_frame_time();
+        if timer <= 0.0
+        {
+            self.held = Some((dir, REPEAT_RATE));
+            return Some(dir);
+        }
+        self.held = Some((dir, timer));
+        None
+    }
+
+    pub fn draw(&self, maze: &Maze, canvas: &Canvas, grid_config: &GridConfig)
+    {
+        let cell_size = grid_config.cell_size;
+        let grid_width = grid_config.grid_width;
+        let fog = &self.fog;
+
+        let revealing = fog.settings.enabled && fog.settings.reveal_image;
+        if revealing
+        {
+            self.draw_revealed_image(canvas, grid_config);
+        }
+
+        let trail_color = if revealing { REVEALED_TRAIL_COLOR } else { TRAIL_COLOR };
+        for (i, _) in self.trail.iter().enumerate().filter(|(_, visited)| **visited)
+        {
+            let x = (i % grid_width) as f32 * cell_size + grid_config.offset.0;
+            let y = (i / grid_width) as f32 * cell_size + grid_config.offset.1;
+            draw_rectangle(x, y, cell_size, cell_size, trail_color);
+        }
+
+        if fog.is_seen(maze.end) { maze.draw_ends(grid_config); }
+        maze.draw_walls(grid_config, |i|
+        {
+            if fog.is_visible(i) { Some(WHITE) }
+            else if fog.is_seen(i) { Some(SEEN_WALL_COLOR) }
+            else { None }
+        });
+        maze.puzzle.draw(grid_config, |i| fog.is_seen(i), |i| self.picked_up[i]);
+
+        let x = (self.player % grid_width) as f32 * cell_size + grid_config.offset.0;
+        let y = (self.player / grid_width) as f32 * cell_size + grid_config.offset.1;
+        draw_circle(x + cell_size * 0.5, y + cell_size * 0.5, cell_size * 0.35, PLAYER_COLOR);
+
+        let status = match self.finished
+        {
+            Some(time) => format!("Finished in {:.2}s with {} moves", time.as_secs_f32(), self.moves),
+            None => format!("{:.1}s   {} moves", self.elapsed().as_secs_f32(), self.moves),
+        };
+        let status = if maze.puzzle.collectibles.is_empty() { status }
+        else { format!("{}   {}/{} collected", status, self.collected, maze.puzzle.collectibles.len()) };
+        draw_text(&status, grid_config.offset.0, grid_config.offset.1 - 16.0, 28.0, WHITE);
+    }
+
+    // The part of the source image under each seen cell, dimmed if out of sight
+    fn draw_revealed_image(&self, canvas: &Canvas, grid_config: &GridConfig)
+    {
+        let cell_size = grid_config.cell_size;
+        let grid_width = grid_config.grid_width;
+        let size = canvas.get_size();
+        let source_width = size.x / grid_width as f32;
+        let source_height = size.y / grid_config.grid_height as f32;
+
+        for i in 0..grid_config.grid_size
+        {
+            if !self.fog.is_seen(i) { continue; }
+
+            let (gx, gy) = ((i % grid_width) as f32, (i / grid_width) as f32);
+            let tint = if self.fog.is_visible(i) { WHITE } else { SEEN_IMAGE_TINT };
+
+            let (x, y) = (gx * cell_size + grid_config.offset.0, gy * cell_size + grid_config.offset.1);
+
+            draw_rectangle(x, y, cell_size, cell_size, BLACK);
+            for layer in canvas.layer_order().iter().map(|&l| &canvas.layers()[l]).filter(|layer| layer.settings.visible)
+            {
+                draw_texture_ex(&layer.texture, x, y, Color { a: layer.settings.opacity, ..tint }, DrawTextureParams
+                {
+                    dest_size: Some(vec2(cell_size, cell_size)),
+                    source: Some(Rect::new(gx * source_width, gy * source_height, source_width, source_height)),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
+
+fn score_path() -> PathBuf
+{
+    std::env::current_exe().ok()
+    .and_then(|exe| exe.parent().map(|folder| folder.join(SCORE_FILE)))
+    .unwrap_or_else(|| PathBuf::from(SCORE_FILE))
+}
+
+// One line per score: key time_ms moves
+fn load_scores(key: &str) -> Vec<Score>
+{
+    let Ok(text) = fs::read_to_string(score_path()) else { return Vec::new(); };
+
+    let mut scores: Vec<Score> = text.lines()
+    .filter_map(|line|
+    {
+        let mut parts = line.split_whitespace();
+        if parts.next()? != key { return None; }
+        let time = Duration::from_millis(parts.next()?.parse().ok()?);
+        let moves = parts.next()?.parse().ok()?;
+        Some(Score { time, moves })
+    })
+    .collect();
+
+    scores.sort_by_key(|score| (score.time, score.moves));
+    scores
+}
+
+fn save_scores(key: &str, scores: &[Score])
+{
+    let text = fs::read_to_string(score_path()).unwrap_or_default();
+
+    // Keep other mazes, replace this one
+    let mut lines: Vec<String> = text.lines()
+    .filter(|line| line.split_whitespace().next() != Some(key))
+    .map(str::to_owned)
+    .collect();
+    lines.extend(scores.iter().map(|score| format!("{} {} {}", key, score.time.as_millis(), score.moves)));
+
+    if let Err(e) = fs::write(score_path(), lines.join("\n") + "\n")
+    {
+        println!("Error\n{}", e);
+    }
+}
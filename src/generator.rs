@@ -0,0 +1,913 @@
+
+use macroquad::rand::RandGenerator;
+
+use crate::{GridConfig, image::InputGrid, maze::{Cell, Dir, neighbour, opposite}, symmetry::Symmetry};
+
+const DIRS: [Dir; 4] = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+const WEIGHT_TRIES: usize = 4; // Light cells can be passed over this often before one gets carved anyway
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Phase
+{
+    Carving,
+    PathCarving,
+    Shortcuts,
+    Done
+}
+
+// Maze generation split into small steps, so it can be animated
+// Runs the same three passes create_maze used to do in one go
+pub struct Generator
+{
+    pub grid: Vec<Cell>,
+    pub visited: Vec<bool>,
+    pub path: Vec<bool>,
+    pub phase: Phase,
+    pub current: Option<usize>, // Last touched cell, for highlighting
+    frontier: Vec<u32>, // Edges as cell * 4 + index in DIRS, every cell adds its edges once, so no set needed
+    blocked: BitSet, // Wall and mask cells, never carved
+    mask: BitSet,
+    weights: Vec<u8>, // Empty without weights
+    path_idx: usize,
+    main_path: Vec<usize>,
+    on_main_path: BitSet,
+    components: UnionFind, // Non main path cells that are connected without going over the main path
+    open_edges: Vec<u32>, // Per component root, open walls to the main path
+    shortcut_idx: usize,
+    symmetry: Symmetry,
+    copies: UnionFind, // Which carved parts are connected, the copies only meet once carving is done
+    grid_width: usize,
+    grid_height: usize,
+    grid_size: usize,
+    unvisited_scan: usize, // Cells before this are all visited or blocked
+    reseeded: bool,
+    pub regions: usize, // Parts the carving couldn't join, only counted after reseeding
+    rng: RandGenerator, // Own one, so generating doesn't reseed the random numbers everything else uses
+}
+
+impl Generator
+{
+    // Same seed and input give the same maze
+    pub fn new(grid_input: Option<InputGrid>, seed: u64, symmetry: Symmetry, grid_config: &GridConfig) -> Self
+    {
+        let rng = RandGenerator::new();
+        rng.srand(seed);
+
+        let grid_width = grid_config.grid_width;
+        let grid_height = grid_config.grid_height;
+        let grid_size = grid_config.grid_size;
+
+        // A failed image load gives back an empty grid
+        let input = grid_input.filter(|input| input.path.len() == grid_size).unwrap_or_else(|| InputGrid::from_path(vec![false; grid_size]));
+
+        let mut blocked = BitSet::new(grid_size);
+        let mut mask = BitSet::new(grid_size);
+        for i in 0..grid_size
+        {
+            if input.walls.get(i) == Some(&true) { blocked.insert(i); }
+            if input.mask.get(i) == Some(&true) { blocked.insert(i); mask.insert(i); }
+        }
+        let weights = if input.weights.len() == grid_size { input.weights } else { Vec::new() };
+
+        let symmetry = symmetry.for_grid(grid_width, grid_height);
+
+        let mut generator = Generator
+        {
+            grid: vec![Cell::new(); grid_size],
+            visited: vec![false; grid_size],
+            path: input.path,
+            phase: Phase::Carving,
+            current: None,
+            frontier: Vec::new(),
+            blocked,
+            mask,
+            weights,
+            path_idx: 0,
+            main_path: Vec::new(),
+            on_main_path: BitSet::new(0),
+            components: UnionFind::new(0),
+            open_edges: Vec::new(),
+            shortcut_idx: 0,
+            symmetry,
+            copies: UnionFind::new(if symmetry == Symmetry::None { 0 } else { grid_size }),
+            grid_width,
+            grid_height,
+            grid_size,
+            unvisited_scan: 0,
+            reseeded: false,
+            regions: 1,
+            rng,
+        };
+
+        // The random start can land on a blocked cell, then the next free one is taken
+        let start = random_start(&generator.rng, grid_width, grid_height);
+        let start = (0..grid_size).map(|i| (start + i) % grid_size).find(|&i| !generator.blocked.contains(i));
+
+        if symmetry != Symmetry::None && generator.carve_axes()
+        {
+            return generator;
+        }
+        if let Some(start) = start
+        {
+            for cell in generator.copies_of(start) { generator.visit(cell); }
+            generator.current = Some(start);
+        }
+
+        generator
+    }
+
+    fn copies_of(&self, cell: usize) -> Vec<usize>
+    {
+        self.symmetry.cells(cell, self.grid_width, self.grid_height)
+    }
+
+    fn visit(&mut self, cell: usize)
+    {
+        if self.visited[cell] || self.blocked.contains(cell) { return; }
+
+        self.visited[cell] = true;
+        self.frontier.extend((0..4).map(|i| (cell * 4 + i) as u32));
+    }
+
+    fn open_wall(&mut self, cell: usize, neighbour: usize)
+    {
+        let Some(dir) = DIRS.into_iter().find(|dir| self::neighbour(cell, dir, self.grid_width, self.grid_size) == Some(neighbour)) else { return; };
+
+        self.grid[cell].set_wall(&dir, false);
+        self.grid[neighbour].set_wall(&opposite(&dir), false);
+        if self.symmetry != Symmetry::None { self.copies.union(cell, neighbour); }
+    }
+
+    // Cells a mirror or turn maps onto themselves get carved straight first and carving grows out from them,
+    // a cell on the axis reached from both sides would close a loop otherwise
+    // False if there are none
+    fn carve_axes(&mut self) -> bool
+    {
+        let on_axis: Vec<usize> = (0..self.grid_size)
+        .filter(|&cell| !self.blocked.contains(cell) && self.copies_of(cell)[1..].contains(&cell))
+        .collect();
+
+        for &cell in on_axis.iter()
+        {
+            self.visit(cell);
+            for dir in [Dir::Right, Dir::Down]
+            {
+                if let Some(neighbour) = neighbour(cell, &dir, self.grid_width, self.grid_size)
+                && on_axis.binary_search(&neighbour).is_ok()
+                {
+                    self.open_wall(cell, neighbour);
+                }
+            }
+        }
+
+        self.current = on_axis.first().copied();
+        !on_axis.is_empty()
+    }
+
+    // Runs until something visible changed, returns true once everything is done
+    pub fn step(&mut self) -> bool
+    {
+        match self.phase
+        {
+            Phase::Carving => self.carve_step(),
+            Phase::PathCarving => self.path_step(),
+            Phase::Shortcuts => self.shortcut_step(),
+            Phase::Done => {}
+        }
+
+        self.phase == Phase::Done
+    }
+
+    pub fn run(&mut self)
+    {
+        while !self.step() {}
+    }
+
+    pub fn finish(mut self) -> Vec<Cell>
+    {
+        self.run();
+        self.grid
+    }
+
+    // Not yet visited cells next to the carved area
+    pub fn frontier(&self) -> impl Iterator<Item = usize> + '_
+    {
+        self.frontier.iter()
+        .filter_map(|&edge| neighbour(edge as usize / 4, &DIRS[edge as usize % 4], self.grid_width, self.grid_size))
+        .filter(|cell| !self.visited[*cell] && !self.blocked.contains(*cell))
+    }
+
+    fn carve_step(&mut self)
+    {
+        let grid_width = self.grid_width;
+        let grid_size = self.grid_size;
+
+        while !self.frontier.is_empty()
+        {
+            let idx = self.pick_edge();
+            let edge = self.frontier.swap_remove(idx) as usize;
+            let (cell, dir) = (edge / 4, &DIRS[edge % 4]);
+
+            let Some(neighbour) = neighbour(cell, dir, grid_width, grid_size) else { continue; };
+            if self.blocked.contains(neighbour) { continue; }
+
+            if self.visited[cell] != self.visited[neighbour]
+            {
+                self.grid[cell].set_wall(dir, false);
+                self.grid[neighbour].set_wall(&opposite(dir), false);
+                self.visit(neighbour);
+                self.current = Some(neighbour);
+
+                if self.symmetry != Symmetry::None { self.carve_copies(cell, neighbour); }
+                return;
+            }
+        }
+
+        // Painted walls can cut off parts the carving never gets to, each of them starts again on its own
+        if let Some(cell) = self.next_unvisited()
+        {
+            for cell in self.copies_of(cell) { self.visit(cell); }
+            self.current = Some(cell);
+            self.reseeded = true;
+            return;
+        }
+
+        if self.symmetry != Symmetry::None { self.join_copies(); }
+        if self.reseeded { self.regions = self.count_regions(); }
+        self.phase = Phase::PathCarving;
+    }
+
+    fn next_unvisited(&mut self) -> Option<usize>
+    {
+        while self.unvisited_scan < self.grid_size
+        {
+            let cell = self.unvisited_scan;
+            if !self.visited[cell] && !self.blocked.contains(cell) { return Some(cell); }
+            self.unvisited_scan += 1;
+        }
+        None
+    }
+
+    // Carved parts that no open wall connects
+    fn count_regions(&self) -> usize
+    {
+        let mut seen = BitSet::new(self.grid_size);
+        let mut regions = 0;
+
+        for first in (0..self.grid_size).filter(|&cell| self.visited[cell])
+        {
+            if seen.contains(first) { continue; }
+            regions += 1;
+            seen.insert(first);
+
+            let mut stack = vec![first];
+            while let Some(cell) = stack.pop()
+            {
+                for dir in DIRS
+                {
+                    if !self.grid[cell].has_wall(&dir)
+                    && let Some(neighbour) = neighbour(cell, &dir, self.grid_width, self.grid_size)
+                    && !seen.contains(neighbour)
+                    {
+                        seen.insert(neighbour);
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+        regions
+    }
+
+    // Same step on every copy, the first copy is the carved one itself
+    fn carve_copies(&mut self, cell: usize, neighbour: usize)
+    {
+        self.copies.union(cell, neighbour);
+
+        for (from, to) in self.copies_of(cell).into_iter().zip(self.copies_of(neighbour)).skip(1)
+        {
+            if !self.visited[from] || self.visited[to] || self.blocked.contains(to) { continue; }
+
+            self.open_wall(from, to);
+            self.visit(to);
+        }
+    }
+
+    // Copies that grew apart get joined, with all copies of the joining wall opened so it stays symmetric
+    // Walls with fewer copies go first, a wall that is its own mirror image joins without making a loop
+    // A wall only gets opened if all of its copies together still join parts that were apart, otherwise that's a loop
+    fn join_copies(&mut self)
+    {
+        let mut walls = Vec::new();
+        for cell in (0..self.grid_size).filter(|&cell| self.visited[cell])
+        {
+            for dir in [Dir::Right, Dir::Down]
+            {
+                if let Some(neighbour) = neighbour(cell, &dir, self.grid_width, self.grid_size)
+                && self.visited[neighbour]
+                {
+                    walls.push((cell, neighbour));
+                }
+            }
+        }
+
+        // Shuffled, then a stable sort keeps the randomness within each size
+        for i in (1..walls.len()).rev() { walls.swap(i, self.rng.gen_range(0, i + 1)); }
+        let copies = |(cell, neighbour): (usize, usize)|
+        {
+            let mut pairs: Vec<(usize, usize)> = self.copies_of(cell).into_iter().zip(self.copies_of(neighbour)).map(|(a, b)| (a.min(b), a.max(b))).collect();
+            pairs.sort_unstable();
+            pairs.dedup();
+            pairs
+        };
+        let mut walls: Vec<Vec<(usize, usize)>> = walls.into_iter().map(copies).collect();
+        walls.sort_by_key(|pairs| pairs.len());
+
+        for pairs in walls.iter()
+        {
+            if !self.joins_as_tree(pairs) { continue; }
+            for &(a, b) in pairs { self.open_wall(a, b); }
+        }
+
+        // Some grids have no symmetric perfect maze at all, like an even one turned by half: no cell or wall is
+        // its own copy, so the copies can only meet in two places at once. A loop beats a maze in pieces
+        for pairs in walls
+        {
+            if pairs.iter().all(|&(a, b)| self.copies.find(a) == self.copies.find(b)) { continue; }
+
+            for (a, b) in pairs { self.open_wall(a, b); }
+        }
+    }
+
+    // Whether opening all these walls joins only parts that were apart, checked on the parts' roots
+    fn joins_as_tree(&mut self, pairs: &[(usize, usize)]) -> bool
+    {
+        let mut groups: Vec<(usize, usize)> = Vec::new(); // Root of each part touched so far, and which of them it's joined to by now
+        for &(a, b) in pairs
+        {
+            let (a, b) = (self.copies.find(a), self.copies.find(b));
+            let group_of = |groups: &[(usize, usize)], root: usize| groups.iter().find(|(part, _)| *part == root).map_or(root, |&(_, group)| group);
+            let (group_a, group_b) = (group_of(&groups, a), group_of(&groups, b));
+            if group_a == group_b { return false; }
+
+            for entry in groups.iter_mut().filter(|(_, group)| *group == group_b) { entry.1 = group_a; }
+            groups.extend([(a, group_a), (b, group_a)]);
+        }
+        true
+    }
+
+    // Random frontier edge, with weights the heavier cells tend to be carved first
+    fn pick_edge(&self) -> usize
+    {
+        let mut idx = self.rng.gen_range(0, self.frontier.len());
+        if self.weights.is_empty() { return idx; }
+
+        for _ in 0..WEIGHT_TRIES
+        {
+            let edge = self.frontier[idx] as usize;
+            let weight = neighbour(edge / 4, &DIRS[edge % 4], self.grid_width, self.grid_size).map_or(u8::MAX, |cell| self.weights[cell]);
+            if self.rng.gen_range(0, u8::MAX as u32) <= weight as u32 { break; }
+
+            idx = self.rng.gen_range(0, self.frontier.len());
+        }
+        idx
+    }
+
+    // Path carving, one path cell per step
+    fn path_step(&mut self)
+    {
+        let grid_width = self.grid_width;
+        let grid_size = self.grid_size;
+
+        while self.path_idx < self.path.len()
+        {
+            let idx = self.path_idx;
+            self.path_idx += 1;
+
+            if !self.path[idx] { continue; }
+
+            for dir in DIRS
+            {
+                if let Some(neighbour) = neighbour(idx, &dir, grid_width, grid_size)
+                && self.path[neighbour]
+                {
+                    self.grid[idx].set_wall(&dir, false);
+                    self.grid[neighbour].set_wall(&opposite(&dir), false);
+                }
+            }
+            self.current = Some(idx);
+            return;
+        }
+
+        self.start_shortcuts();
+    }
+
+    //Non Path wall removal
+    // First checks all ppaths and picks the longest one,
+    // And then checks, that there are no shortcuts
+    // A side area is a shortcut if it touches the main path anywhere but the cell it's entered from,
+    // so instead of searching it for every path cell, side areas get joined once and count their openings to the path
+    // Works the same for branching paths like text, every side area ends up hanging off one path cell
+    fn start_shortcuts(&mut self)
+    {
+        self.phase = Phase::Done;
+        self.current = None;
+        self.open_mask();
+
+        let Some(main_path) = main_path(&self.path, self.grid_width, self.grid_size) else { return; };
+
+        self.on_main_path = BitSet::from_cells(&main_path, self.grid_size);
+        self.main_path = main_path;
+        self.join_side_areas();
+        self.phase = Phase::Shortcuts;
+    }
+
+    // Masked cells become one open space, closed off from the maze around them
+    fn open_mask(&mut self)
+    {
+        for cell in (0..self.grid_size).filter(|&cell| self.mask.contains(cell))
+        {
+            for dir in [Dir::Right, Dir::Down]
+            {
+                if let Some(neighbour) = neighbour(cell, &dir, self.grid_width, self.grid_size)
+                && self.mask.contains(neighbour)
+                {
+                    self.grid[cell].set_wall(&dir, false);
+                    self.grid[neighbour].set_wall(&opposite(&dir), false);
+                }
+            }
+        }
+    }
+
+    fn join_side_areas(&mut self)
+    {
+        let (grid_width, grid_size) = (self.grid_width, self.grid_size);
+
+        self.components = UnionFind::new(grid_size);
+        for cell in (0..grid_size).filter(|&cell| !self.on_main_path.contains(cell))
+        {
+            // Right and down are enough to see every wall once
+            for dir in [Dir::Right, Dir::Down]
+            {
+                if !self.grid[cell].has_wall(&dir)
+                && let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size)
+                && !self.on_main_path.contains(neighbour)
+                {
+                    self.components.union(cell, neighbour);
+                }
+            }
+        }
+
+        self.open_edges = vec![0; grid_size];
+        for &cell in self.main_path.iter()
+        {
+            for dir in DIRS
+            {
+                if !self.grid[cell].has_wall(&dir)
+                && let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size)
+                && !self.on_main_path.contains(neighbour)
+                {
+                    let root = self.components.find(neighbour);
+                    self.open_edges[root] += 1;
+                }
+            }
+        }
+    }
+
+    // One main path cell per step
+    fn shortcut_step(&mut self)
+    {
+        let grid_width = self.grid_width;
+        let grid_size = self.grid_size;
+
+        let Some(&cell) = self.main_path.get(self.shortcut_idx) else
+        {
+            self.phase = Phase::Done;
+            self.current = None;
+            return;
+        };
+        self.shortcut_idx += 1;
+        self.current = Some(cell);
+
+        for dir in DIRS
+        {
+            if self.grid[cell].has_wall(&dir) { continue; }
+
+            let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size) else { continue; };
+            if self.on_main_path.contains(neighbour) { continue; }
+
+            // Any opening of the area besides the ones into this cell leads back to the path somewhere else
+            let root = self.components.find(neighbour);
+            if self.open_edges[root] > self.openings_into(cell, root)
+            {
+                self.grid[cell].set_wall(&dir, true);
+                self.grid[neighbour].set_wall(&opposite(&dir), true);
+                self.open_edges[root] -= 1;
+            }
+        }
+    }
+
+    // Open walls between the path cell and the side area
+    fn openings_into(&mut self, cell: usize, root: usize) -> u32
+    {
+        let mut openings = 0;
+        for dir in DIRS
+        {
+            if !self.grid[cell].has_wall(&dir)
+            && let Some(neighbour) = neighbour(cell, &dir, self.grid_width, self.grid_size)
+            && !self.on_main_path.contains(neighbour)
+            && self.components.find(neighbour) == root
+            {
+                openings += 1;
+            }
+        }
+        openings
+    }
+}
+
+pub fn create_maze(grid_input: Option<InputGrid>, seed: u64, symmetry: Symmetry, grid_config: &GridConfig) -> Vec<Cell>
+{
+    Generator::new(grid_input, seed, symmetry, grid_config).finish()
+}
+
+
+// Flood Search, to get all paths, if there are multiple (so it works not with only one path)
+pub fn find_path_component(path: &[bool], grid_width: usize, grid_size: usize) -> Vec<Vec<usize>>
+{
+    let mut visited = vec![false; grid_size];
+    let mut paths = Vec::new();
+
+    for idx in 0..grid_size
+    {
+        if !path[idx] || visited[idx] { continue; }
+
+        // Individual Path, flood search to get each one
+        let mut component = Vec::new();
+        let mut stack = vec![idx];
+        visited[idx] = true;
+
+        while let Some(cell) = stack.pop()
+        {
+            component.push(cell);
+
+            for dir in DIRS
+            {
+                if let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size)
+                && path[neighbour] && !visited[neighbour]
+                {
+                    visited[neighbour] = true;
+                    stack.push(neighbour);
+                }
+            }
+        }
+        paths.push(component);
+    }
+
+    paths
+}
+
+// Longest path in the input, reversed so the first of equally long paths wins
+pub fn main_path(path: &[bool], grid_width: usize, grid_size: usize) -> Option<Vec<usize>>
+{
+    find_path_component(path, grid_width, grid_size).into_iter().rev().max_by_key(|part| part.len())
+}
+
+// Ends of the same path generation treats as the main path
+pub fn main_path_ends(path: &[bool], grid_width: usize, grid_size: usize) -> Option<(usize, usize)>
+{
+    path_ends(&main_path(path, grid_width, grid_size)?, grid_width, grid_size)
+}
+
+// Gets the two end-points of a path, will return None for anything that has not two (like a point with 1, or a circle with none, or a tree with multiple ends)
+fn path_ends(path: &[usize], grid_width: usize, grid_size: usize) -> Option<(usize, usize)>
+{
+    let mut ends = Vec::new();
+    let path_set = BitSet::from_cells(path, grid_size);
+
+    for &cell in path.iter()
+    {
+        let mut neighbour_amount = 0;
+        for dir in DIRS
+        {
+            if let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size)
+            && path_set.contains(neighbour)
+            {
+                neighbour_amount += 1;
+            }
+        }
+
+        if neighbour_amount == 1
+        {
+            ends.push(cell);
+        }
+    }
+
+    if ends.len() == 2
+    {
+        return Some((ends[0], ends[1]));
+    }
+    None
+}
+
+fn random_start(rng: &RandGenerator, width: usize, height: usize) -> usize
+{
+    let x = rng.gen_range(0, width);
+    let y = rng.gen_range(0, height);
+
+    y * width + x
+}
+
+// One bit per cell
+struct BitSet
+{
+    words: Vec<u64>,
+}
+
+impl BitSet
+{
+    fn new(size: usize) -> Self
+    {
+        BitSet { words: vec![0; size.div_ceil(64)] }
+    }
+
+    fn from_cells(cells: &[usize], size: usize) -> Self
+    {
+        let mut set = BitSet::new(size);
+        for &cell in cells { set.insert(cell); }
+        set
+    }
+
+    fn insert(&mut self, cell: usize)
+    {
+        self.words[cell / 64] |= 1 << (cell % 64);
+    }
+
+    fn contains(&self, cell: usize) -> bool
+    {
+        self.words[cell / 64] & (1 << (cell % 64)) != 0
+    }
+}
+
+// Disjoint sets with path halving and union by size, u32 keeps it at 8 bytes a cell
+struct UnionFind
+{
+    parent: Vec<u32>,
+    size: Vec<u32>,
+}
+
+impl UnionFind
+{
+    fn new(size: usize) -> Self
+    {
+        UnionFind { parent: (0..size as u32).collect(), size: vec![1; size] }
+    }
+
+    fn find(&mut self, mut cell: usize) -> usize
+    {
+        while self.parent[cell] as usize != cell
+        {
+            let grandparent = self.parent[self.parent[cell] as usize];
+            self.parent[cell] = grandparent;
+            cell = grandparent as usize;
+        }
+        cell
+    }
+
+    fn union(&mut self, a: usize, b: usize)
+    {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b { return; }
+        if self.size[a] < self.size[b] { std::mem::swap(&mut a, &mut b); }
+
+        self.parent[b] = a as u32;
+        self.size[a] += self.size[b];
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::bench::serpentine;
+
+    // The shortcut removal join_side_areas and shortcut_step replaced, a BFS through the side area for every opening
+    fn bfs_shortcuts(grid: &mut [Cell], main_path: &[usize], grid_width: usize, grid_size: usize)
+    {
+        let on_main_path = BitSet::from_cells(main_path, grid_size);
+
+        for &cell in main_path
+        {
+            for dir in DIRS
+            {
+                if grid[cell].has_wall(&dir) { continue; }
+
+                if let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size)
+                && !on_main_path.contains(neighbour)
+                && reaches_path_elsewhere(neighbour, cell, &on_main_path, grid, grid_width, grid_size)
+                {
+                    grid[cell].set_wall(&dir, true);
+                    grid[neighbour].set_wall(&opposite(&dir), true);
+                }
+            }
+        }
+    }
+
+    fn reaches_path_elsewhere(start: usize, entry: usize, on_main_path: &BitSet, grid: &[Cell], grid_width: usize, grid_size: usize) -> bool
+    {
+        let mut visited = vec![false; grid_size];
+        let mut queue = VecDeque::from([start]);
+        visited[start] = true;
+
+        while let Some(cell) = queue.pop_front()
+        {
+            for dir in DIRS
+            {
+                if grid[cell].has_wall(&dir) { continue; }
+                let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size) else { continue; };
+
+                if on_main_path.contains(neighbour)
+                {
+                    if neighbour != entry { return true; }
+                    continue;
+                }
+                if !visited[neighbour]
+                {
+                    visited[neighbour] = true;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        false
+    }
+
+    // A straight line through the middle row, the side areas above and below touch it everywhere
+    fn line(grid_width: usize, grid_height: usize) -> Vec<bool>
+    {
+        let mut path = vec![false; grid_width * grid_height];
+        let row = grid_height / 2 * grid_width;
+        path[row + 1..row + grid_width - 1].fill(true);
+        path
+    }
+
+    // Upside down U, the area inside only opens to the path and the bottom
+    fn arch(grid_width: usize, grid_height: usize) -> Vec<bool>
+    {
+        let mut path = vec![false; grid_width * grid_height];
+        let (left, right, top, bottom) = (grid_width / 4, grid_width * 3 / 4, grid_height / 4, grid_height * 3 / 4);
+        for x in left..=right { path[top * grid_width + x] = true; }
+        for y in top..=bottom
+        {
+            path[y * grid_width + left] = true;
+            path[y * grid_width + right] = true;
+        }
+        path
+    }
+
+    fn checksum(grid: &[Cell]) -> u64
+    {
+        grid.iter().fold(0xcbf29ce484222325u64, |hash, cell| (hash ^ cell.to_bits() as u64).wrapping_mul(0x100000001b3))
+    }
+
+    #[test]
+    fn shortcuts_match_bfs()
+    {
+        let (grid_width, grid_height) = (41, 29);
+        let grid_config = GridConfig::new(0.0, 0.0, grid_width, grid_height, 1.0, (0.0, 0.0));
+
+        let mut closed = 0;
+        for (name, path) in [("serpentine", serpentine(grid_width, grid_height)), ("line", line(grid_width, grid_height)), ("arch", arch(grid_width, grid_height))]
+        {
+            for seed in [1, 2, 3, 42, 1234]
+            {
+                let mut generator = Generator::new(Some(InputGrid::from_path(path.clone())), seed, Symmetry::None, &grid_config);
+                while generator.phase != Phase::Shortcuts
+                {
+                    assert!(!generator.step(), "{} seed {} never got to the shortcuts", name, seed);
+                }
+
+                let before = generator.grid.clone();
+                let mut expected = before.clone();
+                bfs_shortcuts(&mut expected, &generator.main_path, grid_width, grid_config.grid_size);
+                let grid = generator.finish();
+
+                assert_eq!(checksum(&grid), checksum(&expected), "{} seed {}", name, seed);
+                closed += before.iter().zip(grid.iter()).filter(|(a, b)| a.to_bits() != b.to_bits()).count();
+            }
+        }
+        assert!(closed > 0, "no shortcuts were closed, the comparison proves nothing");
+    }
+
+    // A closed ring of wall cells, carving has to start again on whichever side it didn't start on
+    #[test]
+    fn wall_ring_carves_both_sides()
+    {
+        let (grid_width, grid_height) = (20, 16);
+        let grid_config = GridConfig::new(0.0, 0.0, grid_width, grid_height, 1.0, (0.0, 0.0));
+
+        let mut walls = vec![false; grid_config.grid_size];
+        for y in 4..=11
+        {
+            for x in 6..=13
+            {
+                if x == 6 || x == 13 || y == 4 || y == 11 { walls[y * grid_width + x] = true; }
+            }
+        }
+
+        for seed in 0..20
+        {
+            let input = InputGrid { walls: walls.clone(), ..InputGrid::from_path(vec![false; grid_config.grid_size]) };
+            let mut generator = Generator::new(Some(input), seed, Symmetry::None, &grid_config);
+            while generator.phase == Phase::Carving { generator.step(); }
+
+            let free = (0..grid_config.grid_size).filter(|&cell| !walls[cell]);
+            assert!(free.clone().all(|cell| generator.visited[cell]), "seed {} left cells uncarved", seed);
+            assert!(free.clone().all(|cell| generator.grid[cell].to_bits() != 0b1111), "seed {} left a sealed cell", seed);
+            assert_eq!(generator.regions, 2, "seed {}", seed);
+        }
+    }
+
+    // The generator owns its random numbers, nothing else running in between changes the maze
+    #[test]
+    fn seed_gives_same_maze()
+    {
+        let grid_config = GridConfig::new(0.0, 0.0, 64, 48, 1.0, (0.0, 0.0));
+        let bits = |with_path: bool, seed: u64| -> Vec<u8>
+        {
+            let input = with_path.then(|| InputGrid::from_path(serpentine(64, 48)));
+            create_maze(input, seed, Symmetry::None, &grid_config).iter().map(Cell::to_bits).collect()
+        };
+
+        for with_path in [false, true]
+        {
+            let first = bits(with_path, 7);
+            macroquad::rand::srand(99); // The global generator isn't used, this mustn't matter
+            assert_eq!(first, bits(with_path, 7));
+            assert_ne!(first, bits(with_path, 8));
+        }
+    }
+
+    // Every symmetry the maze view offers still gives a perfect maze, and the walls match across the copies
+    #[test]
+    fn symmetric_mazes_are_perfect()
+    {
+        for (grid_width, grid_height) in [(15, 15), (16, 16), (15, 10), (16, 10), (16, 15)]
+        {
+            let grid_config = GridConfig::new(0.0, 0.0, grid_width, grid_height, 1.0, (0.0, 0.0));
+            let grid_size = grid_config.grid_size;
+            let open = |grid: &[Cell], a: usize, b: usize| DIRS.iter().any(|dir| neighbour(a, dir, grid_width, grid_size) == Some(b) && !grid[a].has_wall(dir));
+
+            for symmetry in Symmetry::GRID
+            {
+                let symmetry = symmetry.for_grid(grid_width, grid_height);
+
+                // Every copy of a tree has the same middle cell or wall, grids without such a spot are left to the loop fallback
+                let fixed_cell = (0..grid_size).any(|cell| symmetry.cells(cell, grid_width, grid_height).iter().all(|&copy| copy == cell));
+                let fixed_wall = (0..grid_size).any(|cell| DIRS.iter().filter_map(|dir| neighbour(cell, dir, grid_width, grid_size)).any(|other|
+                    symmetry.cells(cell, grid_width, grid_height).into_iter().zip(symmetry.cells(other, grid_width, grid_height)).all(|pair| pair == (cell, other) || pair == (other, cell))));
+                if !fixed_cell && !fixed_wall { continue; }
+
+                for seed in 0..10
+                {
+                    let grid = create_maze(None, seed, symmetry, &grid_config);
+                    let name = format!("{} {}x{} seed {}", symmetry.name(), grid_width, grid_height, seed);
+
+                    let edges: Vec<(usize, usize)> = (0..grid_size)
+                    .flat_map(|cell| [Dir::Right, Dir::Down].into_iter().filter_map(move |dir| Some((cell, dir, neighbour(cell, &dir, grid_width, grid_size)?))))
+                    .filter(|(cell, dir, _)| !grid[*cell].has_wall(dir))
+                    .map(|(cell, _, other)| (cell, other))
+                    .collect();
+                    assert_eq!(edges.len(), grid_size - 1, "{} has loops or parts apart", name);
+
+                    let mut regions = UnionFind::new(grid_size);
+                    for &(a, b) in edges.iter() { regions.union(a, b); }
+                    assert!((0..grid_size).all(|cell| regions.find(cell) == regions.find(0)), "{} isn't connected", name);
+
+                    for &(a, b) in edges.iter()
+                    {
+                        let copies = symmetry.cells(a, grid_width, grid_height).into_iter().zip(symmetry.cells(b, grid_width, grid_height));
+                        assert!(copies.into_iter().all(|(a, b)| open(&grid, a, b)), "{} isn't symmetric at {}-{}", name, a, b);
+                    }
+                }
+            }
+        }
+    }
+
+    // Once 0/3 and 1/2 are joined parts, opening 0-1 and 2-3 together would join them twice
+    #[test]
+    fn joins_as_tree_spots_loops()
+    {
+        let grid_config = GridConfig::new(0.0, 0.0, 4, 1, 1.0, (0.0, 0.0));
+        let mut generator = Generator::new(None, 0, Symmetry::Vertical, &grid_config);
+        generator.copies = UnionFind::new(grid_config.grid_size);
+
+        assert!(generator.joins_as_tree(&[(0, 1), (2, 3)]));
+        generator.copies.union(0, 3);
+        assert!(generator.joins_as_tree(&[(0, 1), (2, 3)]));
+        assert!(!generator.joins_as_tree(&[(0, 3)]));
+
+        generator.copies.union(1, 2);
+        assert!(!generator.joins_as_tree(&[(0, 1), (2, 3)]));
+        assert!(generator.joins_as_tree(&[(0, 1)]));
+    }
+}
@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+
+use macroquad::{miniquad, rand::srand};
+
+use crate::{GridConfig, ends::{EndsSettings, EndsStrategy, Side}, heatmap::{HeatmapSettings, Palette}, history::History, image::InputGrid, maze::Maze, recorder::{RecordFormat, RecordSettings, Recorder}, symmetry::Symmetry, techniques::SolverAlgorithm};
+
+const USAGE: &str = "Usage: maze_images --record <file.gif|file.png|folder> [--format gif|apng|png] [--size 512x512] [--grid 30x30] [--image <path> | --text <text> [--font <file.ttf>]] [--steps-per-frame 1] [--delay 40] [--seed <n>] [--ends manual|farthest|path|<side>-<side>] [--solver bfs|dead-end|left-hand|right-hand|tremaux|pledge] [--heatmap viridis|inferno|rainbow|grayscale] [--symmetry vertical|horizontal|both|rotate2|rotate4]";
+
+pub struct HeadlessOptions
+{
+    pub output: PathBuf,
+    pub settings: RecordSettings,
+    pub grid_width: usize,
+    pub grid_height: usize,
+    pub image: Option<String>,
+    pub text: Option<String>,
+    pub font: String, // Empty for the bundled one
+    pub seed: Option<u64>,
+    pub ends: EndsSettings,
+    pub solver: SolverAlgorithm,
+    pub heatmap: Option<Palette>,
+    pub symmetry: Symmetry,
+}
+
+// Everything after the program name
+pub fn parse(args: &[String]) -> Result<HeadlessOptions, String>
+{
+    let mut output = None;
+    let mut format = None;
+    let mut settings = RecordSettings::default();
+    let (mut grid_width, mut grid_height) = (30, 30);
+    let mut image = None;
+    let mut text = None;
+    let mut font = String::new();
+    let mut seed = None;
+    let mut ends = EndsSettings::default();
+    let mut solver = SolverAlgorithm::Bfs;
+    let mut heatmap = None;
+    let mut symmetry = Symmetry::None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next()
+    {
+        let mut value = || args.next().cloned().ok_or(format!("Missing value for {}\n{}", arg, USAGE));
+
+        match arg.as_str()
+        {
+            "--record" => output = Some(PathBuf::from(value()?)),
+            "--format" => format = Some(match value()?.as_str()
+            {
+                "gif" => RecordFormat::Gif,
+                "apng" => RecordFormat::Apng,
+                "png" => RecordFormat::PngSequence,
+                other => return Err(format!("Unknown format {}\n{}", other, USAGE)),
+            }),
+            "--size" =>
+            {
+                let (w, h) = parse_size(&value()?)?;
+                (settings.width, settings.height) = (w as u32, h as u32);
+            },
+            "--grid" => (grid_width, grid_height) = parse_size(&value()?)?,
+            "--image" => image = Some(value()?),
+            "--text" => text = Some(value()?),
+            "--font" => font = value()?,
+            "--steps-per-frame" => settings.steps_per_frame = value()?.parse().map_err(|e| format!("{}", e))?,
+            "--delay" => settings.frame_delay_ms = value()?.parse().map_err(|e| format!("{}", e))?,
+            "--seed" => seed = Some(value()?.parse().map_err(|e| format!("{}", e))?),
+            "--ends" => ends = parse_ends(&value()?)?,
+            "--heatmap" =>
+            {
+                let name = value()?;
+                heatmap = Some(Palette::ALL.into_iter().find(|palette| palette.name().eq_ignore_ascii_case(&name))
+                .ok_or(format!("Unknown palette {}\n{}", name, USAGE))?);
+            },
+            "--symmetry" => symmetry = match value()?.as_str()
+            {
+                "none" => Symmetry::None,
+                "vertical" => Symmetry::Vertical,
+                "horizontal" => Symmetry::Horizontal,
+                "both" => Symmetry::Both,
+                "rotate2" => Symmetry::Rotational(2),
+                "rotate4" => Symmetry::Rotational(4),
+                other => return Err(format!("Unknown symmetry {}\n{}", other, USAGE)),
+            },
+            "--solver" => solver = match value()?.as_str()
+            {
+                "bfs" => SolverAlgorithm::Bfs,
+                "dead-end" => SolverAlgorithm::DeadEndFilling,
+                "left-hand" => SolverAlgorithm::LeftHand,
+                "right-hand" => SolverAlgorithm::RightHand,
+                "tremaux" => SolverAlgorithm::Tremaux,
+                "pledge" => SolverAlgorithm::Pledge,
+                other => return Err(format!("Unknown solver {}\n{}", other, USAGE)),
+            },
+            other => return Err(format!("Unknown argument {}\n{}", other, USAGE)),
+        }
+    }
+
+    let output = output.ok_or(USAGE.to_owned())?;
+    if image.is_some() && text.is_some() { return Err(format!("Use either --image or --text\n{}", USAGE)); }
+
+    // Guess from the file name if not given
+    settings.format = format.unwrap_or(match output.extension().and_then(|ext| ext.to_str())
+    {
+        Some("gif") => RecordFormat::Gif,
+        Some("png") | Some("apng") => RecordFormat::Apng,
+        _ => RecordFormat::PngSequence,
+    });
+
+    Ok(HeadlessOptions { output, settings, grid_width, grid_height, image, text, font, seed, ends, solver, heatmap, symmetry })
+}
+
+fn parse_size(value: &str) -> Result<(usize, usize), String>
+{
+    let (w, h) = value.split_once('x').ok_or(format!("Expected WIDTHxHEIGHT, got {}", value))?;
+    let w = w.parse().map_err(|_| format!("Expected WIDTHxHEIGHT, got {}", value))?;
+    let h = h.parse().map_err(|_| format!("Expected WIDTHxHEIGHT, got {}", value))?;
+    if w == 0 || h == 0 { return Err(format!("Size can't be zero, got {}", value)); }
+
+    Ok((w, h))
+}
+
+// manual, farthest, path or two sides like left-right
+fn parse_ends(value: &str) -> Result<EndsSettings, String>
+{
+    let strategy = match value
+    {
+        "manual" => EndsStrategy::Manual,
+        "farthest" => EndsStrategy::Diameter,
+        "path" => EndsStrategy::ImagePath,
+        _ =>
+        {
+            let (start, end) = value.split_once('-').ok_or(format!("Unknown ends {}\n{}", value, USAGE))?;
+            return Ok(EndsSettings { strategy: EndsStrategy::Sides, start_side: parse_side(start)?, end_side: parse_side(end)?, open_border: true });
+        }
+    };
+
+    Ok(EndsSettings { strategy, ..EndsSettings::default() })
+}
+
+fn parse_side(value: &str) -> Result<Side, String>
+{
+    match value
+    {
+        "top" => Ok(Side::Top),
+        "bottom" => Ok(Side::Bottom),
+        "left" => Ok(Side::Left),
+        "right" => Ok(Side::Right),
+        other => Err(format!("Unknown side {}\n{}", other, USAGE)),
+    }
+}
+
+// Generates and solves one maze without a window, recording every step
+pub fn run(options: HeadlessOptions)
+{
+    srand(options.seed.unwrap_or(miniquad::date::now() as u64));
+
+    let grid_config = GridConfig::new(options.settings.width as f32, options.settings.height as f32, options.grid_width, options.grid_height, 40.0, (0.0, 0.0));
+    let mut maze = Maze::new(&grid_config);
+    let mut history = History::new();
+
+    let mut grid = options.image.as_deref().map(|path| crate::image::get_grid_from_path(path, &grid_config).0);
+    if let Some(text) = &options.text
+    {
+        match crate::text::text_path(text, &options.font, &grid_config)
+        {
+            Ok(path) => grid = Some(InputGrid::from_path(path)),
+            Err(e) => { println!("Error\n{}", e); return; }
+        }
+    }
+
+    maze.end = grid_config.grid_size - 1;
+    maze.ends = options.ends;
+    maze.solver.algorithm = options.solver;
+    maze.symmetry = options.symmetry;
+    if let Some(palette) = options.heatmap { maze.set_heatmap(HeatmapSettings { enabled: true, palette }, &grid_config); }
+    maze.regenerate_maze(grid, 0.1, &grid_config, true);
+
+    let mut recorder = match Recorder::new(options.settings, &options.output)
+    {
+        Ok(recorder) => recorder,
+        Err(e) => { println!("Error\n{}", e); return; }
+    };
+    if let Err(e) = recorder.capture(&maze, &grid_config) { println!("Error\n{}", e); return; }
+    maze.recorder = Some(recorder);
+
+    while maze.is_animating()
+    {
+        maze.advance(&grid_config, &mut history);
+    }
+
+    if let Some(recorder) = maze.recorder.take()
+    {
+        match recorder.finish()
+        {
+            Ok(frames) => println!("Wrote {} frames to {}", frames, options.output.display()),
+            Err(e) => println!("Error\n{}", e),
+        }
+    }
+}
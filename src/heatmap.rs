@@ -0,0 +1,106 @@
+use macroquad::prelude::*;
+
+use crate::{GridConfig, maze::Cell, solver};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Palette
+{
+    Viridis,
+    Inferno,
+    Rainbow,
+    Grayscale,
+}
+
+impl Palette
+{
+    pub const ALL: [Palette; 4] = [Palette::Viridis, Palette::Inferno, Palette::Rainbow, Palette::Grayscale];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Palette::Viridis => "Viridis",
+            Palette::Inferno => "Inferno",
+            Palette::Rainbow => "Rainbow",
+            Palette::Grayscale => "Grayscale",
+        }
+    }
+
+    // t from 0 (near) to 1 (far)
+    pub fn color(self, t: f32) -> Color
+    {
+        let t = t.clamp(0.0, 1.0);
+        match self
+        {
+            Palette::Viridis => gradient(&[(0.27, 0.0, 0.33), (0.23, 0.32, 0.55), (0.13, 0.57, 0.55), (0.37, 0.79, 0.38), (0.99, 0.91, 0.14)], t),
+            Palette::Inferno => gradient(&[(0.0, 0.0, 0.02), (0.34, 0.06, 0.43), (0.73, 0.21, 0.33), (0.98, 0.55, 0.04), (0.99, 1.0, 0.64)], t),
+            Palette::Rainbow => macroquad::color::hsl_to_rgb(0.75 * (1.0 - t), 0.85, 0.5),
+            Palette::Grayscale => Color::new(t, t, t, 1.0),
+        }
+    }
+}
+
+fn gradient(stops: &[(f32, f32, f32)], t: f32) -> Color
+{
+    let scaled = t * (stops.len() - 1) as f32;
+    let i = (scaled as usize).min(stops.len() - 2);
+    let f = scaled - i as f32;
+    let (a, b) = (stops[i], stops[i + 1]);
+
+    Color::new(a.0 + (b.0 - a.0) * f, a.1 + (b.1 - a.1) * f, a.2 + (b.2 - a.2) * f, 1.0)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct HeatmapSettings
+{
+    pub enabled: bool,
+    pub palette: Palette,
+}
+
+impl Default for HeatmapSettings
+{
+    fn default() -> Self
+    {
+        HeatmapSettings { enabled: false, palette: Palette::Viridis }
+    }
+}
+
+// Distance of every cell to the source, walking through the maze
+pub struct Heatmap
+{
+    pub settings: HeatmapSettings,
+    pub source: Option<usize>, // None follows the maze start
+    pub distances: Vec<Option<usize>>,
+    pub max_distance: usize,
+}
+
+impl Heatmap
+{
+    pub fn new() -> Self
+    {
+        Heatmap { settings: HeatmapSettings::default(), source: None, distances: Vec::new(), max_distance: 0 }
+    }
+
+    // Every step costs the same, so BFS gives the same result as Dijkstra
+    pub fn recompute(&mut self, start: usize, grid: &[Cell], grid_config: &GridConfig)
+    {
+        let source = self.source.filter(|&source| source < grid.len()).unwrap_or(start);
+        self.distances = solver::distances(source, grid, grid_config);
+        self.max_distance = self.distances.iter().flatten().copied().max().unwrap_or(0);
+    }
+
+    // None for unreachable cells
+    pub fn color(&self, cell: usize) -> Option<Color>
+    {
+        let distance = (*self.distances.get(cell)?)?;
+        Some(self.settings.palette.color(distance as f32 / self.max_distance.max(1) as f32))
+    }
+}
+
+impl Default for Heatmap
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::{GridConfig, canvas::Canvas, maze::{Dir, Maze, MazeState}, puzzle::Puzzle};
+
+const HISTORY_BUDGET: usize = 128 * 1024 * 1024; // Bytes kept for undo, oldest edits get dropped first
+const MAX_EDITS: usize = 200;
+
+pub enum Edit
+{
+    // Only the changed rectangle of one canvas layer, before and after the stroke
+    CanvasPatch { layer: usize, x: u32, y: u32, width: u32, height: u32, before: Vec<u8>, after: Vec<u8> },
+    // Whole layer swap (image loads)
+    CanvasImage { layer: usize, before: Image, after: Image },
+    // Toggling a wall is its own inverse, it clears the puzzle though, so that's kept for undo
+    MazeWall { cell: usize, dir: Dir, puzzle: Puzzle },
+    // Regenerations and end placement, with the ends, seed and puzzle that belong to the walls
+    MazeGrid { before: MazeState, after: MazeState },
+    // Several edits that were done by one action (image load + regeneration)
+    Group(Vec<Edit>),
+}
+
+impl Edit
+{
+    fn bytes(&self) -> usize
+    {
+        match self
+        {
+            Edit::CanvasPatch { before, after, .. } => before.len() + after.len(),
+            Edit::CanvasImage { before, after, .. } => before.bytes.len() + after.bytes.len(),
+            Edit::MazeWall { .. } => std::mem::size_of::<Edit>(),
+            Edit::MazeGrid { before, after } => before.cells.len() + after.cells.len(),
+            Edit::Group(edits) => edits.iter().map(|edit| edit.bytes()).sum(),
+        }
+    }
+
+    fn apply(&self, undo: bool, canvas: &mut Canvas, maze: &mut Maze, grid_config: &GridConfig)
+    {
+        match self
+        {
+            Edit::CanvasPatch { layer, x, y, width, height, before, after } =>
+            {
+                canvas.write_region(*layer, *x, *y, *width, *height, if undo { before } else { after });
+            },
+            Edit::CanvasImage { layer, before, after } =>
+            {
+                canvas.set_layer_image(*layer, if undo { before.clone() } else { after.clone() });
+            },
+            Edit::MazeWall { cell, dir, puzzle } =>
+            {
+                maze.toggle_wall(*cell, *dir, grid_config);
+                if undo { maze.set_puzzle(puzzle.clone(), grid_config); }
+            },
+            Edit::MazeGrid { before, after } =>
+            {
+                maze.restore(if undo { before } else { after }, grid_config);
+            },
+            Edit::Group(edits) =>
+            {
+                if undo
+                {
+                    for edit in edits.iter().rev() { edit.apply(undo, canvas, maze, grid_config); }
+                }
+                else
+                {
+                    for edit in edits.iter() { edit.apply(undo, canvas, maze, grid_config); }
+                }
+            }
+        }
+    }
+}
+
+pub struct History
+{
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    bytes: usize,
+}
+
+impl Default for History
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl History
+{
+    pub fn new() -> Self
+    {
+        History
+        {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            bytes: 0,
+        }
+    }
+
+    pub fn push(&mut self, edit: Edit)
+    {
+        for old in self.redo.drain(..)
+        {
+            self.bytes -= old.bytes();
+        }
+
+        self.bytes += edit.bytes();
+        self.undo.push_back(edit);
+
+        // Always keep at least the newest edit, even if it alone is over budget
+        while self.undo.len() > 1 && (self.bytes > HISTORY_BUDGET || self.undo.len() > MAX_EDITS)
+        {
+            if let Some(old) = self.undo.pop_front()
+            {
+                self.bytes -= old.bytes();
+            }
+        }
+    }
+
+    pub fn undo(&mut self, canvas: &mut Canvas, maze: &mut Maze, grid_config: &GridConfig)
+    {
+        if let Some(edit) = self.undo.pop_back()
+        {
+            edit.apply(true, canvas, maze, grid_config);
+            self.redo.push(edit);
+        }
+    }
+
+    pub fn redo(&mut self, canvas: &mut Canvas, maze: &mut Maze, grid_config: &GridConfig)
+    {
+        if let Some(edit) = self.redo.pop()
+        {
+            edit.apply(false, canvas, maze, grid_config);
+            self.undo.push_back(edit);
+        }
+    }
+
+    pub fn can_undo(&self) -> bool
+    {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool
+    {
+        !self.redo.is_empty()
+    }
+}
+
+// Smallest rectangle containing every changed pixel, None if nothing changed
+pub fn canvas_patch(layer: usize, before: &[u8], after: &Image) -> Option<Edit>
+{
+    let width = after.width();
+    let height = after.height();
+    if before.len() != after.bytes.len() { return None; }
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (usize::MAX, usize::MAX, 0, 0);
+
+    for y in 0..height
+    {
+        let row = y * width * 4;
+        for x in 0..width
+        {
+            let i = row + x * 4;
+            if before[i..i + 4] != after.bytes[i..i + 4]
+            {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+    }
+
+    if min_x == usize::MAX { return None; }
+
+    let patch_width = max_x - min_x + 1;
+    let patch_height = max_y - min_y + 1;
+    let copy = |bytes: &[u8]|
+    {
+        let mut out = Vec::with_capacity(patch_width * patch_height * 4);
+        for y in min_y..=max_y
+        {
+            let start = (y * width + min_x) * 4;
+            out.extend_from_slice(&bytes[start..start + patch_width * 4]);
+        }
+        out
+    };
+
+    Some(Edit::CanvasPatch
+    {
+        layer,
+        x: min_x as u32,
+        y: min_y as u32,
+        width: patch_width as u32,
+        height: patch_height as u32,
+        before: copy(before),
+        after: copy(&after.bytes),
+    })
+}
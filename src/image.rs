@@ -1,10 +1,63 @@
 use image::{DynamicImage, GrayImage, ImageBuffer, Luma};
-use macroquad::{color::BLACK, texture::Image};
+use macroquad::{color::{BLACK, Color, WHITE}, texture::Image};
 
 use crate::GridConfig;
 
+// What a drawn color means for generation, decided by the color's main channel:
+// white is path, red is wall, blue is mask, green is weight (brighter is carved sooner), black is nothing
+pub const ROLE_COLORS: [(&str, Color); 4] =
+[
+    ("Path", WHITE),
+    ("Wall", Color::new(0.9, 0.2, 0.2, 1.0)),
+    ("Mask", Color::new(0.2, 0.4, 0.95, 1.0)),
+    ("Weight", Color::new(0.2, 0.9, 0.3, 1.0)),
+];
+pub const NEUTRAL_WEIGHT: u8 = 128; // Cells that weren't painted with a weight color
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ColorRole
+{
+    Path,
+    Wall,   // Never carved, every wall stays
+    Mask,   // Left out of the maze, opened up into plain space
+    Weight(f32),
+}
+
+pub fn color_role(color: Color) -> Option<ColorRole>
+{
+    if color.a < 0.5 { return None; }
+
+    let max = color.r.max(color.g).max(color.b);
+    let min = color.r.min(color.g).min(color.b);
+
+    // Grays, bright enough counts as path like plain white always did
+    if max - min < 0.2 { return (max >= 0.5).then_some(ColorRole::Path); }
+
+    if color.r == max { Some(ColorRole::Wall) }
+    else if color.b == max { Some(ColorRole::Mask) }
+    else { Some(ColorRole::Weight(color.g)) }
+}
+
+// The input image boiled down to one value per cell and role
+pub struct InputGrid
+{
+    pub path: Vec<bool>,
+    pub walls: Vec<bool>,
+    pub mask: Vec<bool>,
+    pub weights: Vec<u8>, // Empty if nothing was painted with a weight color
+}
+
+impl InputGrid
+{
+    // Only a path, like the edge detected images give
+    pub fn from_path(path: Vec<bool>) -> Self
+    {
+        InputGrid { path, walls: Vec::new(), mask: Vec::new(), weights: Vec::new() }
+    }
+}
+
 
-pub fn get_grid_from_path(path: &str, grid_config: &GridConfig) -> (Vec<bool>, Image) 
+pub fn get_grid_from_path(path: &str, grid_config: &GridConfig) -> (InputGrid, Image) 
 {
     let mut input = match image::open(path) 
     {
@@ -12,7 +65,7 @@ pub fn get_grid_from_path(path: &str, grid_config: &GridConfig) -> (Vec<bool>, I
         Err(e) => 
         {
             println!("Error\n{}", e);
-            return (Vec::new(), Image::gen_image_color(16, 16, BLACK));
+            return (InputGrid::from_path(Vec::new()), Image::gen_image_color(16, 16, BLACK));
         }
     };
 
@@ -24,20 +77,56 @@ pub fn get_grid_from_path(path: &str, grid_config: &GridConfig) -> (Vec<bool>, I
     get_input_grid(&macroquad_image, grid_config)
 }
 
-pub fn get_grid_from_image(image: Image, grid_config: &GridConfig) -> (Vec<bool>, Image) 
+// Any image file as it is, for tracing over
+pub fn load_image(path: &str) -> Result<Image, String>
+{
+    let input = image::open(path).map_err(|e| e.to_string())?.to_rgba8();
+    let (width, height) = input.dimensions();
+    if width > u16::MAX as u32 || height > u16::MAX as u32 { return Err(format!("{} is too big", path)); }
+
+    Ok(Image { bytes: input.into_raw(), width: width as u16, height: height as u16 })
+}
+
+// PNG with alpha, so empty stays empty when it's loaded again
+pub fn save_image(path: &str, input: &Image) -> Result<(), String>
+{
+    image::save_buffer_with_format(path, &input.bytes, input.width as u32, input.height as u32, image::ColorType::Rgba8, image::ImageFormat::Png)
+    .map_err(|e| e.to_string())
+}
+
+// Stretched to the given size
+pub fn fit_image(input: &Image, width: u16, height: u16) -> Image
+{
+    let Some(buffer) = image::RgbaImage::from_raw(input.width as u32, input.height as u32, input.bytes.clone())
+    else { return Image::gen_image_color(width, height, Color::new(0.0, 0.0, 0.0, 0.0)); };
+
+    let resized = image::imageops::resize(&buffer, width as u32, height as u32, image::imageops::FilterType::Triangle);
+    Image { bytes: resized.into_raw(), width, height }
+}
+
+pub fn get_grid_from_image(image: Image, grid_config: &GridConfig) -> (InputGrid, Image) 
 {
     get_input_grid(&image, grid_config)
 }
 
-pub fn get_input_grid(input: &Image, grid_config: &GridConfig) -> (Vec<bool>, Image)
+// A cell takes the role most of its pixels have, if that's at least half of them
+pub fn get_input_grid(input: &Image, grid_config: &GridConfig) -> (InputGrid, Image)
 {
     let grid_width = grid_config.grid_width;
     let grid_height = grid_config.grid_height;
+    let grid_size = grid_config.grid_size;
 
-    let mut grid: Vec<bool> = vec![false; grid_config.grid_size];
+    let mut grid = InputGrid
+    {
+        path: vec![false; grid_size],
+        walls: vec![false; grid_size],
+        mask: vec![false; grid_size],
+        weights: vec![NEUTRAL_WEIGHT; grid_size],
+    };
+    let mut any_weight = false;
 
-    let image_width = input.width() as usize;
-    let image_height = input.height() as usize;
+    let image_width = input.width();
+    let image_height = input.height();
 
     for gy in 0..grid_height
     {
@@ -49,26 +138,47 @@ pub fn get_input_grid(input: &Image, grid_config: &GridConfig) -> (Vec<bool>, Im
             let x0 = (gx * image_width) / grid_width;
             let x1 = ((gx + 1) * image_width) / grid_width;
 
-            let mut white = 0;
+            // Path, wall, mask, weight
+            let mut counts = [0; 4];
+            let mut weight_sum = 0.0;
             for y in y0..y1
             {
                 for x in x0..x1
                 {
-                    let pixel = input.get_pixel(x as u32, y as u32);
-                    if pixel.r == 1.0 { white += 1; }
+                    match color_role(input.get_pixel(x as u32, y as u32))
+                    {
+                        Some(ColorRole::Path) => counts[0] += 1,
+                        Some(ColorRole::Wall) => counts[1] += 1,
+                        Some(ColorRole::Mask) => counts[2] += 1,
+                        Some(ColorRole::Weight(weight)) => { counts[3] += 1; weight_sum += weight; },
+                        None => {},
+                    }
                 }
             }
 
             let cell_pixel_count = (x1 - x0) * (y1 - y0);
+            let i = gy * grid_width + gx;
+
+            // First role wins a tie, so path beats the rest
+            let (role, &count) = counts.iter().enumerate().rev().max_by_key(|(_, count)| **count).unwrap_or((0, &0));
+            if count == 0 || count * 2 < cell_pixel_count { continue; }
 
-            if white * 2 >= cell_pixel_count
+            match role
             {
-                // grid.push(gy * grid_width + gx);
-                grid[gy * grid_width + gx] = true;
+                0 => grid.path[i] = true,
+                1 => grid.walls[i] = true,
+                2 => grid.mask[i] = true,
+                _ =>
+                {
+                    grid.weights[i] = (weight_sum / count as f32 * 255.0) as u8;
+                    any_weight = true;
+                },
             }
         }
     }
 
+    if !any_weight { grid.weights.clear(); }
+
     (grid, input.clone())
 }
 
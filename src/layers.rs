@@ -0,0 +1,118 @@
+use macroquad::prelude::*;
+
+use crate::{canvas::ERASE_COLOR, image::color_role};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LayerKind
+{
+    Reference, // Photo to trace over
+    Edges,     // Edge detected input image
+    Path,
+    Mask,
+}
+
+impl LayerKind
+{
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            LayerKind::Reference => "Reference",
+            LayerKind::Edges => "Edges",
+            LayerKind::Path => "Path",
+            LayerKind::Mask => "Mask",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct LayerSettings
+{
+    pub visible: bool,
+    pub opacity: f32,
+    pub feeds_generation: bool, // Only these get flattened into the input grid
+}
+
+pub struct Layer
+{
+    pub kind: LayerKind,
+    pub settings: LayerSettings,
+    pub image: Image,
+    pub texture: Texture2D,
+}
+
+impl Layer
+{
+    pub fn new(kind: LayerKind, width: u16, height: u16) -> Self
+    {
+        let settings = match kind
+        {
+            LayerKind::Reference => LayerSettings { visible: true, opacity: 0.5, feeds_generation: false },
+            _ => LayerSettings { visible: true, opacity: 1.0, feeds_generation: true },
+        };
+
+        let image = Image::gen_image_color(width, height, ERASE_COLOR);
+        let texture = Texture2D::from_image(&image);
+        texture.set_filter(FilterMode::Nearest);
+
+        Layer { kind, settings, image, texture }
+    }
+
+    // Call after changing the image
+    pub fn refresh(&mut self)
+    {
+        if self.texture.width() as u16 == self.image.width && self.texture.height() as u16 == self.image.height
+        {
+            self.texture.update(&self.image);
+        }
+        else
+        {
+            self.texture = Texture2D::from_image(&self.image);
+            self.texture.set_filter(FilterMode::Nearest);
+        }
+    }
+}
+
+// Bottom to top, reference under everything so drawings stay readable
+pub fn default_layers(width: u16, height: u16) -> Vec<Layer>
+{
+    [LayerKind::Reference, LayerKind::Edges, LayerKind::Path, LayerKind::Mask].into_iter()
+    .map(|kind| Layer::new(kind, width, height))
+    .collect()
+}
+
+// Layers that feed generation flattened in draw order, the top one wins where several are painted
+// They all have the canvas size (set_layer_image scales loaded images to it), one that doesn't would come out
+// sheared, so it's left out instead
+pub fn flatten(layers: &[Layer], order: &[usize]) -> Image
+{
+    let Some(first) = layers.first() else { return Image::empty(); };
+    let (width, height) = (first.image.width, first.image.height);
+    let mut out = Image::gen_image_color(width, height, ERASE_COLOR);
+
+    for layer in order.iter().filter_map(|&i| layers.get(i)).filter(|layer| layer.settings.feeds_generation)
+    {
+        if (layer.image.width, layer.image.height) != (width, height)
+        {
+            println!("Error\n{} layer is {}x{}, not {}x{}, it's left out", layer.kind.name(), layer.image.width, layer.image.height, width, height);
+            continue;
+        }
+
+        for (dst, src) in out.bytes.chunks_exact_mut(4).zip(layer.image.bytes.chunks_exact(4))
+        {
+            if src[3] >= 128 { dst.copy_from_slice(src); }
+        }
+    }
+
+    out
+}
+
+// Pixels that mean nothing for generation become see through, like black around detected edges
+pub fn clear_empty(image: &mut Image)
+{
+    for pixel in image.bytes.chunks_exact_mut(4)
+    {
+        let color = Color::from_rgba(pixel[0], pixel[1], pixel[2], pixel[3]);
+        if color_role(color).is_none() { pixel.copy_from_slice(&[0, 0, 0, 0]); }
+    }
+}
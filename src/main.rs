@@ -1,15 +1,39 @@
-use std::time::{Duration, Instant};
-
 use macroquad::prelude::*;
 use macroquad::rand::srand;
 
+pub mod animation;
+pub mod game;
+pub mod generator;
+pub mod headless;
 pub mod image;
 pub mod maze;
 pub mod solver;
 pub mod ui;
 pub mod canvas;
+pub mod fog;
+pub mod history;
+pub mod recorder;
+pub mod puzzle;
+pub mod ends;
+pub mod techniques;
+pub mod heatmap;
+pub mod render;
+pub mod bench;
+pub mod camera;
+pub mod shapes;
+pub mod text;
+pub mod layers;
+pub mod symmetry;
+pub mod fill;
+pub mod brush;
 
-use crate::canvas::Canvas;
+use crate::canvas::{BrushSettings, Canvas};
+use crate::game::Game;
+use crate::history::{Edit, History};
+use crate::image::InputGrid;
+use crate::layers::LayerKind;
+use crate::puzzle::Puzzle;
+use crate::recorder::Recorder;
 use crate::ui::{InputImage, UI, UiCommand};
 
 pub fn window_config() -> Conf 
@@ -23,17 +47,35 @@ pub fn window_config() -> Conf
     }
 }
 
-#[macroquad::main(window_config)]
-async fn main() 
+fn main()
+{
+    // --record means headless recording, no window, anything else (like a file from "open with") still opens it
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "--bench")
+    {
+        if let Err(e) = bench::run(&args[1..]) { println!("{}", e); }
+        return;
+    }
+    if args.iter().any(|arg| arg == "--record")
+    {
+        match headless::parse(&args)
+        {
+            Ok(options) => headless::run(options),
+            Err(e) => println!("{}", e),
+        }
+        return;
+    }
+
+    macroquad::Window::from_config(window_config(), run());
+}
+
+async fn run() 
 {
     srand(miniquad::date::now() as u64);
 
-    let mut grid_config = GridConfig::new(screen_width(), screen_height(), 10, 10, 40.0, (400.0, 50.0));
+    let mut grid_config = GridConfig::layout(screen_width(), screen_height(), 10, 10);
     let mut maze = maze::Maze::new(&grid_config);
 
-    let mut timer = Instant::now();
-    let time_stop = Duration::from_millis(10);
-
     let mut ui = UI::new();
 
     let mut block_input: bool = false;
@@ -46,49 +88,70 @@ async fn main()
 
     let mut state = AppState::Maze;
 
-    let mut brush_size = 6.0;
-    let mut smoothing = 0.5;
+    let mut brush = BrushSettings::default();
+
+    let mut history = History::new();
 
-    let mut color = WHITE;
+    let mut game = Game::new(&maze, &grid_config);
 
     loop 
     {
-        clear_background(Color::new(0.164705882, 0.164705882, 0.164705882, 1.0));
+        clear_background(Color::from_rgba(42, 42, 42, 255));
+
+        // Layout follows the window, the grid itself stays the same
+        if (screen_width(), screen_height()) != (grid_config.width, grid_config.height)
+        {
+            let resized = GridConfig::layout(screen_width(), screen_height(), grid_config.grid_width, grid_config.grid_height);
+            canvas.resize_view(vec2(grid_config.width, grid_config.height), vec2(resized.width, resized.height));
+            grid_config = resized;
+        }
 
         match state
         {
             AppState::Draw =>
             {
-                canvas.update(block_input, brush_size, smoothing, color, &grid_config);
-                canvas.draw(&grid_config);
+                canvas.update(block_input, &brush, &grid_config, &mut history);
+                canvas.draw(&brush, &grid_config);
             },
             AppState::Maze =>
             {
-                maze.update(&mut timer, &time_stop, block_input, &grid_config);
+                maze.update(block_input, &grid_config, &mut history);
                 maze.draw(&grid_config);
+            },
+            AppState::Play =>
+            {
+                if !ui.typing() { game.update(&maze, &grid_config); }
+                game.draw(&maze, &canvas, &grid_config);
             }
         }
 
-        block_input = ui.draw(&state, &mut brush_size, &mut smoothing, color);
-        ui.update();
+        block_input = ui.draw(&state, &mut brush, &history, &maze, &game, &canvas);
+        ui.update(&state);
 
         for command in ui.drain_commands()
         {
             match command
             {
-                UiCommand::RegenerateMaze { use_image, threshold } => 
+                UiCommand::RegenerateMaze { use_image, threshold, animate } => 
                 {
+                    let mut edits = Vec::new();
+
                     let grid = if use_image == InputImage::Image
                     {
                         let (grid, image) = crate::image::get_grid_from_path(ui.get_path(), &grid_config);
 
-                        canvas.set_image(image);
+                        // Only the edges layer gets replaced, drawings and the reference stay
+                        let size = canvas.get_size();
+                        let mut edges = crate::image::fit_image(&image, size.x as u16, size.y as u16);
+                        layers::clear_empty(&mut edges);
+
+                        edits.push(canvas.replace_layer(canvas.layer_of(LayerKind::Edges), edges));
 
                         Some(grid)
                     }
                     else if use_image == InputImage::Drawing
                     {
-                        let (grid, _) = crate::image::get_grid_from_image(canvas.get_image(), &grid_config);
+                        let (grid, _) = crate::image::get_grid_from_image(canvas.generation_image(), &grid_config);
 
                         Some(grid)
                     }
@@ -97,15 +160,170 @@ async fn main()
                         None
                     };
                     
-                    maze.regenerate_maze(grid, threshold, &grid_config);
+                    edits.extend(maze.regenerate_maze(grid, threshold, &grid_config, animate));
+                    if !edits.is_empty() { history.push(Edit::Group(edits)); }
+                    game.reset(&maze, &grid_config);
+                },
+                UiCommand::SwitchState(new_state) =>
+                {
+                    if new_state == AppState::Play
+                    {
+                        maze.finish_generation(&grid_config, &mut history);
+                        game.reset(&maze, &grid_config);
+                    }
+                    state = new_state;
+                },
+                UiCommand::RestartGame => game.reset(&maze, &grid_config),
+                UiCommand::SetFog(settings) => game.set_fog(settings, &maze, &grid_config),
+                UiCommand::PlacePuzzle(settings) =>
+                {
+                    maze.place_puzzle(settings, &grid_config);
+                    game.reset(&maze, &grid_config);
+                },
+                UiCommand::SetWaypointOrder(order) =>
+                {
+                    maze.waypoint_order = order;
+                    maze.restart_solver(&grid_config);
+                },
+                UiCommand::ClearWaypoints =>
+                {
+                    maze.waypoints.clear();
+                    maze.restart_solver(&grid_config);
+                },
+                UiCommand::SetEnds(settings) => maze.ends = settings,
+                UiCommand::ApplyEnds =>
+                {
+                    if let Some(edit) = maze.place_ends(&grid_config) { history.push(edit); }
+                    game.reset(&maze, &grid_config);
+                },
+                UiCommand::SetSolverAlgorithm(algorithm) =>
+                {
+                    maze.solver.algorithm = algorithm;
+                    maze.restart_solver(&grid_config);
+                },
+                UiCommand::SetHeatmap(settings) => maze.set_heatmap(settings, &grid_config),
+                UiCommand::ResetHeatmapSource => maze.set_heatmap_source(None, &grid_config),
+                UiCommand::FitView => match state
+                {
+                    AppState::Draw => canvas.fit_view(),
+                    _ => maze.camera.fit(&grid_config),
+                },
+                UiCommand::ActualSize => match state
+                {
+                    AppState::Draw => canvas.actual_size(),
+                    _ => maze.camera.actual_size(&grid_config),
+                },
+                UiCommand::ResetView => match state
+                {
+                    AppState::Draw => canvas.reset_view(),
+                    _ => maze.camera.reset(&grid_config),
+                },
+                UiCommand::ClearPuzzle =>
+                {
+                    maze.puzzle = Puzzle::default();
+                    game.reset(&maze, &grid_config);
                 },
-                UiCommand::SwitchState(new_state) => state = new_state,
-                UiCommand::SwitchColor(new_color) => color = new_color,
+                UiCommand::SwitchColor(new_color) =>
+                {
+                    brush.color = new_color;
+                    brush.eraser = false;
+                },
+                UiCommand::SetEraser(eraser) => brush.eraser = eraser,
                 UiCommand::ShowGrid(show) => canvas.show_grid(show),
+                UiCommand::SetShapeTool(tool) => canvas.set_tool(tool),
+                UiCommand::TextToCanvas { text, font } =>
+                {
+                    match crate::text::text_path(&text, &font, &grid_config)
+                    {
+                        Ok(path) => canvas.paint_path(&path, &grid_config, &mut history),
+                        Err(e) => println!("Error\n{}", e),
+                    }
+                },
+                UiCommand::TextToMaze { text, font, animate } =>
+                {
+                    match crate::text::text_path(&text, &font, &grid_config)
+                    {
+                        Ok(path) =>
+                        {
+                            if let Some(edit) = maze.regenerate_maze(Some(InputGrid::from_path(path)), 0.1, &grid_config, animate) { history.push(edit); }
+                            game.reset(&maze, &grid_config);
+                        },
+                        Err(e) => println!("Error\n{}", e),
+                    }
+                },
+                UiCommand::SnapToCells(snap) => canvas.set_snap_to_cells(snap),
+                UiCommand::SetSymmetry(symmetry) => canvas.set_symmetry(symmetry),
+                UiCommand::SetMazeSymmetry(symmetry) => maze.symmetry = symmetry,
+                UiCommand::SelectLayer(layer) => canvas.select_layer(layer),
+                UiCommand::SetLayer { layer, settings } => canvas.set_layer_settings(layer, settings),
+                UiCommand::MoveLayer { layer, up } => canvas.move_layer(layer, up),
+                UiCommand::LoadReference(path) =>
+                {
+                    match crate::image::load_image(&path)
+                    {
+                        Ok(image) => history.push(canvas.replace_layer(canvas.layer_of(LayerKind::Reference), image)),
+                        Err(e) => println!("Error\n{}", e),
+                    }
+                },
+                UiCommand::LoadCanvas(path) =>
+                {
+                    match crate::image::load_image(&path)
+                    {
+                        Ok(image) => history.push(canvas.replace_layer(canvas.active_layer(), image)),
+                        Err(e) => println!("Error\n{}", e),
+                    }
+                },
+                UiCommand::SaveCanvas(path) =>
+                {
+                    if let Err(e) = crate::image::save_image(&path, &canvas.generation_image())
+                    {
+                        println!("Error\n{}", e);
+                    }
+                },
                 UiCommand::SwitchFillMode(new_fill) =>
                 {
                     canvas.set_fill(new_fill);
-                }
+                },
+                UiCommand::Undo =>
+                {
+                    history.undo(&mut canvas, &mut maze, &grid_config);
+                    game.reset(&maze, &grid_config);
+                },
+                UiCommand::Redo =>
+                {
+                    history.redo(&mut canvas, &mut maze, &grid_config);
+                    game.reset(&maze, &grid_config);
+                },
+                UiCommand::TogglePlay => maze.playback.toggle(),
+                UiCommand::StepOnce => maze.playback.step_once(),
+                UiCommand::SetStepInterval(interval) => maze.playback.interval = interval,
+                UiCommand::SetStepsPerFrame(steps) => maze.playback.steps_per_tick = steps,
+                UiCommand::SolveInstantly => maze.solve_instantly(&grid_config, &mut history),
+                UiCommand::StepBack => maze.step_back(&grid_config),
+                UiCommand::FinishGeneration => maze.finish_generation(&grid_config, &mut history),
+                UiCommand::StartRecording { settings, path } =>
+                {
+                    match Recorder::new(settings, &path)
+                    {
+                        Ok(mut recorder) =>
+                        {
+                            if let Err(e) = recorder.capture(&maze, &grid_config) { println!("Error\n{}", e); }
+                            maze.recorder = Some(recorder);
+                        },
+                        Err(e) => println!("Error\n{}", e),
+                    }
+                },
+                UiCommand::StopRecording =>
+                {
+                    if let Some(recorder) = maze.recorder.take()
+                    {
+                        match recorder.finish()
+                        {
+                            Ok(frames) => println!("Wrote {} frames", frames),
+                            Err(e) => println!("Error\n{}", e),
+                        }
+                    }
+                },
             }
         }
 
@@ -114,11 +332,16 @@ async fn main()
 }
 
 
+const PANEL_SPACE: f32 = 400.0;
+const LAYOUT_MARGIN: f32 = 50.0;
+const MAX_CELL_SIZE: f32 = 40.0;
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum AppState
 {
     Maze,
-    Draw
+    Draw,
+    Play
 }
 
 
@@ -148,4 +371,16 @@ impl GridConfig
             offset
         }
     }
+
+    // Space on the left for the panel, the maze gets the rest, cells never bigger than MAX_CELL_SIZE
+    pub fn layout(screen_width: f32, screen_height: f32, grid_width: usize, grid_height: usize) -> Self
+    {
+        let left = PANEL_SPACE.min(screen_width * 0.35);
+        let available_w = (screen_width - left - LAYOUT_MARGIN).max(1.0);
+        let available_h = (screen_height - LAYOUT_MARGIN * 2.0).max(1.0);
+
+        let cell_size = (available_w / grid_width as f32).min(available_h / grid_height as f32).min(MAX_CELL_SIZE);
+
+        Self::new(screen_width, screen_height, grid_width, grid_height, cell_size, (left, LAYOUT_MARGIN))
+    }
 }
@@ -1,8 +1,45 @@
-use std::{collections::{HashSet, VecDeque}, time::{Duration, Instant}};
-
-use macroquad::{prelude::*, rand::gen_range};
+use std::time::Duration;
+
+use macroquad::prelude::*;
+
+use crate::{GridConfig, animation::Playback, camera::MazeCamera, ends::{self, EndsSettings, EndsStrategy}, recorder::Recorder, generator::{Generator, Phase, create_maze}, image::InputGrid, heatmap::{Heatmap, HeatmapSettings}, history::{Edit, History}, render::{Line, MazeRenderer, compute_wall_lines}, puzzle::{Puzzle, PuzzleSettings}, solver::{self, Solver}, symmetry::Symmetry, techniques::{SolverAlgorithm, Technique}};
+
+// Shared with the recorder, so exported frames look like the window
+pub const CARVED_COLOR: Color = Color::new(0.25, 0.3, 0.35, 1.0);
+pub const PATH_COLOR: Color = Color::new(0.55, 0.4, 0.7, 1.0);
+pub const FRONTIER_COLOR: Color = Color::new(0.8, 0.5, 0.2, 0.6);
+pub const CURRENT_COLOR: Color = Color::new(0.9, 0.9, 0.9, 0.8);
+pub const VISITED_COLOR: Color = Color::new(0.4, 0.8, 0.4, 1.0);
+pub const SOLUTION_COLOR: Color = Color::new(0.4, 0.4, 0.8, 1.0);
+pub const START_COLOR: Color = Color::new(0.8, 0.8, 0.4, 1.0);
+pub const END_COLOR: Color = Color::new(0.8, 0.4, 0.4, 1.0);
+pub const WAYPOINT_COLOR: Color = Color::new(0.4, 0.75, 0.85, 1.0);
+pub const FILLED_COLOR: Color = Color::new(0.15, 0.15, 0.15, 1.0);
+pub const TRAIL_COLOR: Color = Color::new(0.4, 0.8, 0.4, 0.5);
+pub const RETRACED_COLOR: Color = Color::new(0.85, 0.55, 0.3, 0.7); // Walked more than once
+pub const WALKER_COLOR: Color = Color::new(0.95, 0.95, 0.95, 1.0);
+pub const MARK_ONCE_COLOR: Color = Color::new(0.95, 0.85, 0.3, 1.0);
+pub const MARK_TWICE_COLOR: Color = Color::new(0.9, 0.3, 0.3, 1.0);
+
+pub const MAX_TOUR_WAYPOINTS: usize = 10; // Shortest tour tries every subset, so this has to stay small
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum WaypointOrder
+{
+    InOrder,     // The order they were placed in
+    ShortestTour,
+}
 
-use crate::{GridConfig, solver::Solver};
+// Everything a regeneration replaces, so undo doesn't leave ends or doors pointing into another maze
+#[derive(Clone)]
+pub struct MazeState
+{
+    pub cells: Vec<u8>, // Packed, see Cell::to_bits
+    pub start: usize,
+    pub end: usize,
+    pub seed: u64,
+    pub puzzle: Puzzle,
+}
 
 pub struct Maze
 {
@@ -11,7 +48,21 @@ pub struct Maze
     pub solver: Solver,
     pub start: usize,
     pub end: usize,
-    pub started: bool
+    pub playback: Playback,
+    pub generator: Option<Generator>,
+    generation_before: Option<MazeState>,
+    pub recorder: Option<Recorder>,
+    pub seed: u64,
+    pub puzzle: Puzzle,
+    pub waypoints: Vec<usize>,
+    pub waypoint_order: WaypointOrder,
+    key_stops: usize, // The solver's first stops are puzzle keys, not waypoints
+    pub ends: EndsSettings,
+    pub symmetry: Symmetry, // Used by the next generation
+    path_input: Option<Vec<bool>>, // Kept for placing the ends on the image path
+    pub heatmap: Heatmap,
+    renderer: MazeRenderer,
+    pub camera: MazeCamera,
 }
 
 impl Maze
@@ -19,8 +70,9 @@ impl Maze
 
     pub fn new(grid_config: &GridConfig) -> Self
     {
-        let grid = create_maze(None, 0.1, grid_config);//create_maze(None, 0.1);
-        let lines = compute_wall_lines(&grid, grid_config.grid_width, grid_config.grid_height, grid_config.cell_size, grid_config.offset);
+        let seed = macroquad::rand::rand() as u64;
+        let grid = create_maze(None, seed, Symmetry::None, grid_config);
+        let lines = compute_wall_lines(&grid, grid_config.grid_width, grid_config.grid_height);
 
         Maze
         {
@@ -29,506 +81,602 @@ impl Maze
             solver: Solver::new(0, 100, grid_config),
             start: 0,
             end: 0,
-            started: false
+            playback: Playback::new(Duration::from_millis(10)),
+            generator: None,
+            generation_before: None,
+            recorder: None,
+            seed,
+            puzzle: Puzzle::default(),
+            waypoints: Vec::new(),
+            waypoint_order: WaypointOrder::InOrder,
+            key_stops: 0,
+            ends: EndsSettings::default(),
+            symmetry: Symmetry::None,
+            path_input: None,
+            heatmap: Heatmap::new(),
+            renderer: MazeRenderer::new(),
+            camera: MazeCamera::new(),
         }
     }
 
-    pub fn update(&mut self, timer: &mut Instant, time_stop: &Duration, block_input: bool, grid_config: &GridConfig)
+    pub fn update(&mut self, block_input: bool, grid_config: &GridConfig, history: &mut History)
     {
         
-        if !block_input { self.handle_input(grid_config); }
-        self.update_solver(timer, time_stop, grid_config);
+        if !block_input
+        {
+            self.camera.update(grid_config);
+            self.handle_input(grid_config, history);
+        }
+
+        for _ in 0..self.playback.tick()
+        {
+            self.advance(grid_config, history);
+        }
+    }
+
+    // One generation or solver step, handed to the recorder if one is running
+    pub fn advance(&mut self, grid_config: &GridConfig, history: &mut History)
+    {
+        if !self.is_animating() { return; }
+
+        if self.generator.is_some() { self.update_generator(grid_config, history); }
+        else { self.update_solver(grid_config); }
+
+        if let Some(mut recorder) = self.recorder.take()
+        {
+            if let Err(e) = recorder.step(self, grid_config)
+            {
+                println!("Error\n{}", e);
+            }
+            self.recorder = Some(recorder);
+        }
+    }
+
+    pub fn is_animating(&self) -> bool
+    {
+        self.generator.is_some() || !self.solver.finished
     }
 
-    pub fn draw(&self, grid_config: &GridConfig)
+    pub fn draw(&mut self, grid_config: &GridConfig)
     {
+        let grid_config = &self.camera.view(grid_config);
+
+        if let Some(generator) = &self.generator
+        {
+            self.draw_generation(generator, grid_config);
+            self.draw_ends(grid_config);
+            self.draw_walls(grid_config, |_| Some(WHITE)); // Changes every step, not worth caching
+            return;
+        }
+
         self.draw_solver(grid_config);
         self.draw_ends(grid_config);
-        self.draw_maze(grid_config);
+        self.renderer.draw_walls(&self.lines, grid_config);
+        self.puzzle.draw(grid_config, |_| true, |_| false);
     }
 
-    fn draw_maze(&self, grid_config: &GridConfig)
+    // Color per cell, None skips the cell (used for fog of war)
+    pub fn draw_walls(&self, grid_config: &GridConfig, wall_color: impl Fn(usize) -> Option<Color>)
     {
-        // for line in self.lines.iter()
-        // {
-        //     draw_line(line.x0, line.y0, line.x1, line.y1, 2.0, WHITE);
-        // }
         let cell_size = grid_config.cell_size;
+        let grid = self.generator.as_ref().map_or(&self.grid, |generator| &generator.grid);
 
-        for (i, cell) in self.grid.iter().enumerate()
+        for (i, cell) in grid.iter().enumerate()
         {
+            let Some(color) = wall_color(i) else { continue; };
+
             let x_idx = i % grid_config.grid_width;
             let y_idx = i / grid_config.grid_width;
             let x = x_idx as f32 * cell_size + grid_config.offset.0;
             let y = y_idx as f32 * cell_size + grid_config.offset.1;
             if cell.up
             {
-                draw_line(x, y, x + cell_size, y, 2.0, WHITE);
+                draw_line(x, y, x + cell_size, y, 2.0, color);
             }
             if cell.down
             {
-                draw_line(x, y + cell_size, x + cell_size, y + cell_size, 2.0, WHITE);
+                draw_line(x, y + cell_size, x + cell_size, y + cell_size, 2.0, color);
             }
             if cell.left
             {
-                draw_line(x, y, x, y + cell_size, 2.0, WHITE);
+                draw_line(x, y, x, y + cell_size, 2.0, color);
             }
             if cell.right
             {
-                draw_line(x + cell_size, y, x + cell_size, y + cell_size, 2.0, WHITE);
+                draw_line(x + cell_size, y, x + cell_size, y + cell_size, 2.0, color);
             }
         }
     }
 
-    fn handle_input(&mut self, grid_config: &GridConfig)
+    fn handle_input(&mut self, grid_config: &GridConfig, history: &mut History)
     {
-        if is_mouse_button_released(MouseButton::Left) || is_mouse_button_pressed(MouseButton::Left)// Start
+        let view = self.camera.view(grid_config);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+
+        if is_mouse_button_pressed(MouseButton::Left) && shift // Waypoint, clicking an existing one removes it
+        && let Some(i) = mouse_to_cell(&view)
         {
-            if let Some(i) = mouse_to_cell(grid_config)
+            match self.waypoints.iter().position(|&w| w == i)
             {
-                self.start = i;
-                self.solver.redo(self.start, self.end);
-                println!("Mouse-idx: {}", i);
+                Some(index) => { self.waypoints.remove(index); },
+                None => self.waypoints.push(i),
             }
+            self.restart_solver(grid_config);
         }
 
-        if is_mouse_button_released(MouseButton::Right) || is_mouse_button_pressed(MouseButton::Right) // End
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+
+        if is_mouse_button_pressed(MouseButton::Left) && ctrl && self.heatmap.settings.enabled // Heatmap source
+        && let Some(i) = mouse_to_cell(&view)
         {
-            if let Some(i) = mouse_to_cell(grid_config)
-            {
-                self.end = i;
-                self.solver.redo(self.start, self.end);
-            }
+            self.set_heatmap_source(Some(i), grid_config);
         }
 
-        if is_mouse_button_released(MouseButton::Middle) || is_mouse_button_pressed(MouseButton::Middle)
+        if (is_mouse_button_released(MouseButton::Left) || is_mouse_button_pressed(MouseButton::Left)) && !shift && !ctrl // Start
+        && let Some(i) = mouse_to_cell(&view)
         {
-            if let Some(_) = mouse_to_cell(grid_config)
-            {
-                // self.grid[i] = !self.grid[i];
-            }
+            self.start = i;
+            self.puzzle = Puzzle::default();
+            self.restart_solver(grid_config);
         }
 
-        if is_key_released(KeyCode::Enter) { self.solver.redo(self.start, self.end); }
+        if (is_mouse_button_released(MouseButton::Right) || is_mouse_button_pressed(MouseButton::Right)) // End
+        && let Some(i) = mouse_to_cell(&view)
+        {
+            self.end = i;
+            self.puzzle = Puzzle::default();
+            self.restart_solver(grid_config);
+        }
 
-        if is_key_released(KeyCode::Space) { self.started = !self.started; }
+        if is_mouse_button_released(MouseButton::Middle) && !self.camera.dragged() // Wall edit, dragging pans instead
+        && self.generator.is_none()
+        && let Some((cell, dir)) = mouse_to_wall(&view)
+        {
+            let puzzle = self.puzzle.clone();
+            self.toggle_wall(cell, dir, grid_config);
+            history.push(Edit::MazeWall { cell, dir, puzzle });
+        }
+
+        if is_key_released(KeyCode::Enter) { self.restart_solver(grid_config); }
+
+        if is_key_released(KeyCode::Space) { self.playback.toggle(); }
+    }
+
+    fn update_generator(&mut self, grid_config: &GridConfig, history: &mut History)
+    {
+        if let Some(generator) = self.generator.as_mut()
+        && generator.step()
+        {
+            self.finish_generation(grid_config, history);
+        }
     }
 
+    fn update_solver(&mut self, grid_config: &GridConfig)
+    {
+        self.solver.advance(&self.grid, grid_config);
+    }
 
-    fn update_solver(&mut self, timer: &mut Instant, time_stop: &Duration, grid_config: &GridConfig)
+    // Runs the rest of the solver in one go, still recorded step by step
+    pub fn solve_instantly(&mut self, grid_config: &GridConfig, history: &mut History)
     {
-        if timer.elapsed() >= *time_stop && self.started
+        if self.generator.is_some() { return; }
+
+        if self.recorder.is_none()
         {
-            if !self.solver.found
-            {
-                self.solver.step(&self.grid, grid_config);
-            }
-            else
-            {
-                self.solver.reconstruction_step();
-            }
-            
-            *timer = Instant::now();
+            self.solver.solve(&self.grid, grid_config);
+            return;
+        }
+
+        while self.is_animating()
+        {
+            self.advance(grid_config, history);
         }
     }
 
-    fn draw_solver(&self, grid_config: &GridConfig)
+    pub fn step_back(&mut self, grid_config: &GridConfig)
+    {
+        if self.generator.is_some() { return; }
+
+        self.playback.playing = false;
+        self.solver.step_back(&self.grid, grid_config);
+    }
+
+    fn draw_generation(&self, generator: &Generator, grid_config: &GridConfig)
     {
         let cell_size = grid_config.cell_size;
         let grid_width = grid_config.grid_width;
-        
-        if !self.solver.finished
+
+        for i in 0..generator.grid.len()
         {
-            for i in 0..self.grid.len()
+            let color = if generator.path[i] && generator.phase != Phase::Carving
+            {
+                PATH_COLOR
+            }
+            else if generator.visited[i]
             {
-                if self.solver.visited[i]
-                {
-                    let x = (i % grid_width) as f32 * cell_size + grid_config.offset.0;
-                    let y = (i / grid_width) as f32 * cell_size + grid_config.offset.1;
-                    draw_rectangle(x, y, cell_size, cell_size, Color::new(0.4, 0.8, 0.4, 1.0));
-                }
+                CARVED_COLOR
             }
+            else { continue; };
+
+            let x = (i % grid_width) as f32 * cell_size + grid_config.offset.0;
+            let y = (i / grid_width) as f32 * cell_size + grid_config.offset.1;
+            draw_rectangle(x, y, cell_size, cell_size, color);
         }
 
-        for i in self.solver.final_path.iter()
+        for i in generator.frontier()
         {
             let x = (i % grid_width) as f32 * cell_size + grid_config.offset.0;
             let y = (i / grid_width) as f32 * cell_size + grid_config.offset.1;
-            draw_rectangle(x, y, cell_size, cell_size, Color::new(0.4, 0.4, 0.8, 1.0));
+            draw_rectangle(x, y, cell_size, cell_size, FRONTIER_COLOR);
+        }
+
+        if let Some(i) = generator.current
+        {
+            let x = (i % grid_width) as f32 * cell_size + grid_config.offset.0;
+            let y = (i / grid_width) as f32 * cell_size + grid_config.offset.1;
+            draw_rectangle(x, y, cell_size, cell_size, CURRENT_COLOR);
         }
     }
 
-    fn draw_ends(&self, grid_config: &GridConfig)
+    // Cell colors go through the cached overlay, only what changed since the last frame gets updated
+    fn draw_solver(&mut self, grid_config: &GridConfig)
     {
-        let cell_size = grid_config.cell_size;
-        let grid_width = grid_config.grid_width;
+        if self.solver.redraw_all { self.renderer.invalidate_overlay(); }
+        let changed = std::mem::take(&mut self.solver.changed);
+        self.solver.redraw_all = false;
 
-        let start_x = (self.start % grid_width) as f32 * cell_size + grid_config.offset.0;
-        let start_y = (self.start / grid_width) as f32 * cell_size + grid_config.offset.1;
-        draw_rectangle(start_x, start_y, cell_size as f32, cell_size as f32, Color::new(0.8, 0.8, 0.4, 1.0));
+        let (solver, heatmap) = (&self.solver, &self.heatmap);
+        self.renderer.draw_overlay(&changed, |i| overlay_color(i, solver, heatmap), grid_config);
 
-        let end_x = (self.end % grid_width) as f32 * cell_size + grid_config.offset.0;
-        let end_y = (self.end / grid_width) as f32 * cell_size + grid_config.offset.1;
-        draw_rectangle(end_x, end_y, cell_size as f32, cell_size as f32, Color::new(0.8, 0.4, 0.4, 1.0));
+        if let Some(technique) = &self.solver.technique
+        {
+            self.draw_technique(technique, grid_config);
+        }
     }
 
-    pub fn regenerate_maze(&mut self, grid_input: Option<Vec<bool>>, threshold: f32, grid_config: &GridConfig)
+    // Trémaux marks and the walker, the filled cells and trails are in the overlay
+    fn draw_technique(&self, technique: &Technique, grid_config: &GridConfig)
     {
-        self.grid = create_maze(grid_input, threshold, grid_config);
-        self.lines = compute_wall_lines(&self.grid, grid_config.grid_width, grid_config.grid_height, grid_config.cell_size, grid_config.offset);
-    }
-}
-
-
-fn mouse_to_cell(grid_config: &GridConfig) -> Option<usize>
-{
-    let (mx, my) = mouse_position();
-
-    let cell_size = grid_config.cell_size;
-
-    let mx = mx - grid_config.offset.0;
-    let my = my - grid_config.offset.1;
-
-    if mx < 0.0 || my < 0.0 { return None; }
+        let cell_size = grid_config.cell_size;
+        let grid_width = grid_config.grid_width;
+        let corner = |i: usize| vec2((i % grid_width) as f32 * cell_size + grid_config.offset.0, (i / grid_width) as f32 * cell_size + grid_config.offset.1);
 
-    let cx = (mx / cell_size).floor() as isize;
-    let cy = (my / cell_size).floor() as isize;
+        if technique.algorithm == SolverAlgorithm::DeadEndFilling { return; }
+        if technique.done && technique.failed.is_none() { return; }
 
-    if cx < 0 || cy < 0 { return None; }
+        // A short bar across each marked passage
+        for (&(a, b), &marks) in technique.marks.iter()
+        {
+            let center = (corner(a) + corner(b)) * 0.5 + vec2(cell_size, cell_size) * 0.5;
+            let across = if b == a + 1 { vec2(0.0, cell_size * 0.2) } else { vec2(cell_size * 0.2, 0.0) };
+            let color = if marks > 1 { MARK_TWICE_COLOR } else { MARK_ONCE_COLOR };
+            draw_line(center.x - across.x, center.y - across.y, center.x + across.x, center.y + across.y, 3.0, color);
+        }
 
-    let cx = cx as usize;
-    let cy = cy as usize;
+        let p = corner(technique.pos) + vec2(cell_size, cell_size) * 0.5;
+        let (forward, side) = match technique.facing
+        {
+            Dir::Up => (vec2(0.0, -1.0), vec2(1.0, 0.0)),
+            Dir::Down => (vec2(0.0, 1.0), vec2(1.0, 0.0)),
+            Dir::Left => (vec2(-1.0, 0.0), vec2(0.0, 1.0)),
+            Dir::Right => (vec2(1.0, 0.0), vec2(0.0, 1.0)),
+        };
+        let size = cell_size * 0.35;
+        draw_triangle(p + forward * size, p - forward * size + side * size, p - forward * size - side * size, WALKER_COLOR);
+    }
 
-    if cx as usize >= grid_config.grid_width || cy as usize >= grid_config.grid_height { return None; }
+    pub fn draw_ends(&self, grid_config: &GridConfig)
+    {
+        let cell_size = grid_config.cell_size;
+        let grid_width = grid_config.grid_width;
 
-    Some(cy * grid_config.grid_width + cx)
-}
+        let start_x = (self.start % grid_width) as f32 * cell_size + grid_config.offset.0;
+        let start_y = (self.start / grid_width) as f32 * cell_size + grid_config.offset.1;
+        draw_rectangle(start_x, start_y, cell_size, cell_size, START_COLOR);
 
-#[derive(Clone)]
-pub struct Cell
-{
-    pub up: bool,
-    pub down: bool,
-    pub left: bool,
-    pub right: bool
-}
+        let end_x = (self.end % grid_width) as f32 * cell_size + grid_config.offset.0;
+        let end_y = (self.end / grid_width) as f32 * cell_size + grid_config.offset.1;
+        draw_rectangle(end_x, end_y, cell_size, cell_size, END_COLOR);
 
-impl Cell
-{
-    fn new() -> Self
-    {
-        Cell { up: true, down: true, left: true, right: true }
+        self.draw_waypoints(grid_config);
     }
 
-    fn set_wall(&mut self, dir: &Dir, value: bool)
+    // Numbered in the order the solver visits them
+    fn draw_waypoints(&self, grid_config: &GridConfig)
     {
-        match dir
+        let cell_size = grid_config.cell_size;
+        let grid_width = grid_config.grid_width;
+        for (n, &i) in self.waypoint_stops().iter().enumerate()
         {
-            Dir::Up => self.up = value,
-            Dir::Down => self.down = value,
-            Dir::Left => self.left = value,
-            Dir::Right => self.right = value,
+            let x = (i % grid_width) as f32 * cell_size + grid_config.offset.0;
+            let y = (i / grid_width) as f32 * cell_size + grid_config.offset.1;
+            draw_circle(x + cell_size * 0.5, y + cell_size * 0.5, cell_size * 0.4, WAYPOINT_COLOR);
+
+            let label = (n + 1).to_string();
+            let font_size = (cell_size * 0.7) as u16;
+            let size = measure_text(&label, None, font_size, 1.0);
+            draw_text(&label, x + (cell_size - size.width) * 0.5, y + (cell_size + size.offset_y) * 0.5, font_size as f32, BLACK);
         }
     }
 
-    fn has_wall(&self, dir: &Dir) -> bool
+    // Returns the edit, so it can be grouped with other changes before going into the history
+    // When animated, the edit gets pushed once the generation is done instead
+    pub fn regenerate_maze(&mut self, grid_input: Option<InputGrid>, _threshold: f32, grid_config: &GridConfig, animate: bool) -> Option<Edit>
     {
-        match dir
+        let before = self.state();
+        self.seed = macroquad::rand::rand() as u64;
+        self.puzzle = Puzzle::default();
+        self.path_input = grid_input.as_ref().map(|input| input.path.clone());
+
+        if animate
         {
-            Dir::Up => self.up,
-            Dir::Down => self.down,
-            Dir::Left => self.left,
-            Dir::Right => self.right,
+            self.generator = Some(Generator::new(grid_input, self.seed, self.symmetry, grid_config));
+            self.generation_before = Some(before);
+            self.playback.playing = true;
+            return None;
         }
-    }
-}
-
-fn create_maze(grid_input: Option<Vec<bool>>, threshold: f32, grid_config: &GridConfig) -> Vec<Cell>
-{
-    let grid_width = grid_config.grid_width;
-    let grid_height = grid_config.grid_height;
-    let grid_size = grid_config.grid_size;
-
-    let has_path = grid_input.is_some();
-    let mut path = vec![false; grid_size];
-    if has_path { path = grid_input.unwrap(); }
 
-    let mut grid = vec![Cell::new(); grid_size];
-    let mut visited = vec![false; grid_size];
+        self.set_grid(finished_grid(Generator::new(grid_input, self.seed, self.symmetry, grid_config)), grid_config);
+        self.place_ends(grid_config);
 
-    let mut frontier_set: HashSet<(usize, Dir)> = HashSet::new();
-    let mut frontier_vec: Vec<(usize, Dir)> = Vec::new();
-
-    let start = random_start(grid_width, grid_height);
-    visited[start] = true;
-    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
-    {
-        frontier_vec.push((start, dir));
-        frontier_set.insert((start, dir));
+        Some(Edit::MazeGrid { before, after: self.state() })
     }
 
-    while !frontier_vec.is_empty()
+    // Skips the rest of a running generation
+    pub fn finish_generation(&mut self, grid_config: &GridConfig, history: &mut History)
     {
-        let idx = gen_range(0, frontier_vec.len());
-        let (cell, dir) = frontier_vec.swap_remove(idx);
-        frontier_set.remove(&(cell, dir));
-
-        let temp = neighbour(cell, &dir, grid_width, grid_size);
-        let neighbour = if temp.is_some()
+        if let Some(generator) = self.generator.take()
         {
-            temp.unwrap()
-        }
-        else { continue; };
-
-        if visited[cell] != visited[neighbour]
-        {            
-            grid[cell].set_wall(&dir, false);
-            grid[neighbour].set_wall(&opposite(&dir), false);
-            visited[neighbour] = true;
+            let before = self.generation_before.take().unwrap_or_else(|| self.state());
+            self.set_grid(finished_grid(generator), grid_config);
+            self.place_ends(grid_config);
+            self.playback.playing = false; // Don't roll straight into solving
 
-            for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
-            {
-                if frontier_set.insert((neighbour, dir))
-                {
-                    frontier_vec.push((neighbour, dir));
-                }
-            }
+            history.push(Edit::MazeGrid { before, after: self.state() });
         }
     }
 
-    // Path carving
-    for idx in 0..path.len()
+    // Picks start and end with the current strategy and opens the border there
+    // Returns the wall change for the history, if there was one
+    pub fn place_ends(&mut self, grid_config: &GridConfig) -> Option<Edit>
     {
-        if !path[idx] { continue; }
+        if self.generator.is_some() || self.ends.strategy == EndsStrategy::Manual { return None; }
 
-        for &neighbor in get_path_neighbours(idx, &path, grid_width, grid_size).iter()
+        let Some((start, end)) = ends::choose_ends(&self.ends, &self.grid, self.path_input.as_deref(), grid_config) else
         {
-            if let Some(dir) = direction_to_neighbour(idx, neighbor, grid_width, grid_size)
-            {
-                grid[idx].set_wall(&dir, false);
-                grid[neighbor].set_wall(&opposite(&dir), false);
-            }
-        }
-    }
+            println!("Error\nNo start and end found for this strategy");
+            return None;
+        };
 
+        let before = self.state();
+        let mut grid = std::mem::take(&mut self.grid);
+        (self.start, self.end) = ends::carve_openings(&mut grid, start, end, &self.ends, grid_config);
+        self.set_grid(grid, grid_config);
 
-    //Non Path wall removal
-    // First checks all ppaths and picks the longest one,
-    // then it checks if the path ahs two ends,
-    // And then checks, that there are no shortcuts
-    // Mostly Works, but not all the time, no idea why
-    let all_paths = find_path_component(&path, grid_width, grid_size);
-    let mut max_lenght = 0;
-    let mut path_id = -1;
-    for (i, part) in all_paths.iter().enumerate()
-    {
-        if part.len() > max_lenght
-        {
-            max_lenght = part.len();
-            path_id = i as i32;
-        }
+        let after = self.state();
+        (before.cells != after.cells || (before.start, before.end) != (after.start, after.end)).then_some(Edit::MazeGrid { before, after })
     }
-    if path_id != -1
+
+    pub fn state(&self) -> MazeState
     {
-        let main_path = all_paths[path_id as usize].clone();
-        if let Some((start, end)) = path_ends(&main_path, grid_width, grid_size) // Start End not needed anymore
+        MazeState
         {
-            let main_path_set: HashSet<_> = main_path.iter().cloned().collect();
-
-            for &cell in main_path.iter()
-            {
-                for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
-                {
-                    if grid[cell].has_wall(&dir) { continue; }
-                    
-                    if let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size)
-                    {
-                        if !main_path_set.contains(&neighbour)
-                        {
-                            if can_reach_main_path_via_shortcut(neighbour, &main_path_set, cell, &grid, grid_width, grid_size)
-                            {
-                                grid[cell].set_wall(&dir, true);
-                                grid[neighbour].set_wall(&opposite(&dir), true);
-                            }
-                        }
-                    }
-                }
-            }
+            cells: self.grid.iter().map(Cell::to_bits).collect(),
+            start: self.start,
+            end: self.end,
+            seed: self.seed,
+            puzzle: self.puzzle.clone(),
         }
     }
 
+    // FNV-1a over the walls and the ends, the seed alone doesn't say which maze it is
+    // (image input, symmetry, wall edits and end placement all change it)
+    pub fn fingerprint(&self) -> u64
+    {
+        let bytes = self.grid.iter().map(Cell::to_bits).chain(self.start.to_le_bytes()).chain(self.end.to_le_bytes());
+        bytes.fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
 
-    grid
-}
-
+    // Ends go first, the solver restarts from them
+    pub fn restore(&mut self, state: &MazeState, grid_config: &GridConfig)
+    {
+        (self.start, self.end, self.seed) = (state.start, state.end, state.seed);
+        self.set_grid(state.cells.iter().map(|&bits| Cell::from_bits(bits)).collect(), grid_config);
+        self.set_puzzle(state.puzzle.clone(), grid_config);
+    }
 
-fn can_reach_main_path_via_shortcut(start: usize, main_path: &HashSet<usize>, entry_point: usize, grid: &Vec<Cell>, grid_width: usize, grid_size: usize) -> bool
-{
-    let mut visited = vec![false; grid_size];
-    let mut queue = VecDeque::new();
-    
-    queue.push_back(start);
-    visited[start] = true;
-    
-    while let Some(cell) = queue.pop_front()
-    {
-        for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
-        {
-            if grid[cell].has_wall(&dir) { continue; }
-            
-            if let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size)
-            {
-                if main_path.contains(&neighbour) && neighbour != entry_point // Shortcut
-                {
-                    return true;
-                }
-                
-                if main_path.contains(&neighbour) { continue; }
-                
-                if !visited[neighbour]
-                {
-                    visited[neighbour] = true;
-                    queue.push_back(neighbour);
-                }
-            }
-        }
+    pub fn set_grid(&mut self, grid: Vec<Cell>, grid_config: &GridConfig)
+    {
+        self.generator = None;
+        self.puzzle = Puzzle::default();
+        self.grid = grid;
+        self.lines = compute_wall_lines(&self.grid, grid_config.grid_width, grid_config.grid_height);
+        self.renderer.invalidate_walls();
+        self.restart_solver(grid_config);
     }
-    false
-}
 
+    // Only inner walls, the outer border always stays closed
+    pub fn toggle_wall(&mut self, cell: usize, dir: Dir, grid_config: &GridConfig)
+    {
+        let Some(other) = neighbour(cell, &dir, grid_config.grid_width, grid_config.grid_size) else { return; };
 
+        let value = !self.grid[cell].has_wall(&dir);
+        self.grid[cell].set_wall(&dir, value);
+        self.grid[other].set_wall(&opposite(&dir), value);
 
-// Flood Search, to get all paths, if there are multiple (so it works not with only one path)
-fn find_path_component(path: &Vec<bool>, grid_width: usize, grid_size: usize) -> Vec<Vec<usize>>
-{
-    let mut visited = vec![false; grid_size];
-    let mut paths = Vec::new();
+        self.lines = compute_wall_lines(&self.grid, grid_config.grid_width, grid_config.grid_height);
+        self.renderer.invalidate_walls();
+        self.puzzle = Puzzle::default();
+        self.restart_solver(grid_config);
+    }
 
-    for idx in 0..grid_size
+    // Fetches the puzzle keys, then goes through the waypoints before heading for the end
+    // With every key held no door is in the way anymore, so the waypoint order doesn't need to know about them
+    pub fn restart_solver(&mut self, grid_config: &GridConfig)
     {
-        if !path[idx] || visited[idx] { continue; }
-
-        // Individual Path, flood search to get each one
-        let mut component = Vec::new();
-        let mut stack = vec![idx];
-        visited[idx] = true;
-
-        while let Some(cell) = stack.pop()
+        let mut stops = solver::key_stops(self.start, self.end, &self.grid, &self.puzzle, grid_config);
+        self.key_stops = stops.len();
+        let from = stops.last().copied().unwrap_or(self.start);
+        stops.extend(match self.waypoint_order
         {
-            component.push(cell);
+            WaypointOrder::ShortestTour if self.waypoints.len() <= MAX_TOUR_WAYPOINTS =>
+                solver::shortest_tour(from, &self.waypoints, self.end, &self.grid, grid_config),
+            _ => self.waypoints.clone(),
+        });
+        stops.push(self.end);
+        self.solver.puzzle = self.puzzle.clone();
+        self.solver.redo_route(self.start, stops, &self.grid);
+        if self.heatmap.settings.enabled { self.heatmap.recompute(self.start, &self.grid, grid_config); }
+    }
 
-            for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
-            {
-                if let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size)
-                {
-                    if path[neighbour] && !visited[neighbour]
-                    {
-                        visited[neighbour] = true;
-                        stack.push(neighbour);
-                    }
-                }
-            }
-        }
-        paths.push(component);
+    pub fn set_heatmap(&mut self, settings: HeatmapSettings, grid_config: &GridConfig)
+    {
+        self.heatmap.settings = settings;
+        self.heatmap.recompute(self.start, &self.grid, grid_config);
+        self.renderer.invalidate_overlay();
     }
 
-    paths
-}
+    // None goes back to measuring from the start
+    pub fn set_heatmap_source(&mut self, source: Option<usize>, grid_config: &GridConfig)
+    {
+        self.heatmap.source = source;
+        self.heatmap.recompute(self.start, &self.grid, grid_config);
+        self.renderer.invalidate_overlay();
+    }
 
-// Gets the two end-points of a path, will return None for anything that has not two (like a point with 1, or a circle with none, or a tree with multiple ends)
-fn path_ends(path: &Vec<usize>, grid_width: usize, grid_size: usize) -> Option<(usize, usize)>
-{
-    let mut ends = Vec::new();
-    let path_set: HashSet<_> = path.iter().cloned().collect();
-    
-    for &cell in path.iter()
+    // Not while generating, the walls aren't final yet
+    pub fn place_puzzle(&mut self, settings: PuzzleSettings, grid_config: &GridConfig)
     {
-        let mut neighbour_amount = 0;
-        for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
-        {
-            if let Some(neighbour) = neighbour(cell, &dir, grid_width, grid_size)
-            {
-                if path_set.contains(&neighbour)
-                {
-                    neighbour_amount += 1;
-                }
-            }
-        }
+        if self.generator.is_some() { return; }
+        self.set_puzzle(Puzzle::place(settings, self.start, self.end, &self.grid, grid_config), grid_config);
+    }
 
-        if neighbour_amount == 1
-        {
-            ends.push(cell);
-        }
+    // Waypoints in the order the solver visits them
+    pub fn waypoint_stops(&self) -> &[usize]
+    {
+        &self.solver.stops[self.key_stops..self.solver.stops.len() - 1]
     }
 
-    if ends.len() == 2
+    // The solver has to know about the doors
+    pub fn set_puzzle(&mut self, puzzle: Puzzle, grid_config: &GridConfig)
     {
-        return Some((ends[0], ends[1]));
+        self.puzzle = puzzle;
+        self.restart_solver(grid_config);
     }
-    None
 }
 
 
+// Runs the rest of the generation, the carving only counts the parts, saying so is up to here
+fn finished_grid(mut generator: Generator) -> Vec<Cell>
+{
+    generator.run();
+    if generator.regions > 1 { println!("Error\nThe painted walls split the maze into {} parts that can't be joined", generator.regions); }
+    generator.grid
+}
 
+fn mouse_to_cell(grid_config: &GridConfig) -> Option<usize>
+{
+    let (mx, my) = mouse_position();
 
+    let cell_size = grid_config.cell_size;
 
+    let mx = mx - grid_config.offset.0;
+    let my = my - grid_config.offset.1;
 
+    if mx < 0.0 || my < 0.0 { return None; }
 
+    let cx = (mx / cell_size).floor() as isize;
+    let cy = (my / cell_size).floor() as isize;
 
+    if cx < 0 || cy < 0 { return None; }
 
+    let cx = cx as usize;
+    let cy = cy as usize;
 
+    if cx >= grid_config.grid_width || cy >= grid_config.grid_height { return None; }
 
+    Some(cy * grid_config.grid_width + cx)
+}
 
+// Closest wall of the cell under the mouse
+fn mouse_to_wall(grid_config: &GridConfig) -> Option<(usize, Dir)>
+{
+    let cell = mouse_to_cell(grid_config)?;
+    let (mx, my) = mouse_position();
 
+    let cell_size = grid_config.cell_size;
+    let fx = (mx - grid_config.offset.0) / cell_size - (cell % grid_config.grid_width) as f32;
+    let fy = (my - grid_config.offset.1) / cell_size - (cell / grid_config.grid_width) as f32;
 
+    let distances = [(fy, Dir::Up), (1.0 - fy, Dir::Down), (fx, Dir::Left), (1.0 - fx, Dir::Right)];
+    let (_, dir) = distances.into_iter().min_by(|a, b| a.0.total_cmp(&b.0))?;
 
+    Some((cell, dir))
+}
 
-
-
-#[derive(Eq, Hash, PartialEq, Copy, Clone)]
-pub enum Dir { Up, Down, Left, Right}
-
-fn random_start(width: usize, height: usize) -> usize
+#[derive(Clone)]
+pub struct Cell
 {
-    let x = gen_range(0, width);
-    let y = gen_range(0, height);
-
-    y * width + x
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool
 }
 
-fn direction_to_neighbour(first: usize, second: usize, width: usize, max: usize) -> Option<Dir>
+impl Default for Cell
 {
-    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+    fn default() -> Self
     {
-        if let Some(neighbour) = neighbour(first, &dir, width, max)
-        {
-            if neighbour == second
-            {
-                return Some(dir)
-            }
-        }
+        Self::new()
     }
-    None
 }
 
-fn get_path_neighbours(pos: usize, path_grid: &Vec<bool>, width: usize, max: usize) -> Vec<usize>
+impl Cell
 {
-    let mut neighbours = Vec::new();
+    pub fn new() -> Self
+    {
+        Cell { up: true, down: true, left: true, right: true }
+    }
 
-    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+    pub fn set_wall(&mut self, dir: &Dir, value: bool)
     {
-        if let Some(neighbour) = neighbour(pos, &dir, width, max)
+        match dir
         {
-            if path_grid[neighbour]
-            {
-                neighbours.push(neighbour);
-            }
+            Dir::Up => self.up = value,
+            Dir::Down => self.down = value,
+            Dir::Left => self.left = value,
+            Dir::Right => self.right = value,
         }
     }
-    neighbours
+
+    pub fn has_wall(&self, dir: &Dir) -> bool
+    {
+        match dir
+        {
+            Dir::Up => self.up,
+            Dir::Down => self.down,
+            Dir::Left => self.left,
+            Dir::Right => self.right,
+        }
+    }
+
+    pub fn to_bits(&self) -> u8
+    {
+        self.up as u8 | (self.down as u8) << 1 | (self.left as u8) << 2 | (self.right as u8) << 3
+    }
+
+    pub fn from_bits(bits: u8) -> Self
+    {
+        Cell { up: bits & 1 != 0, down: bits & 2 != 0, left: bits & 4 != 0, right: bits & 8 != 0 }
+    }
 }
 
-fn neighbour(pos: usize, dir: &Dir, width: usize, max: usize) -> Option<usize>
+
+
+#[derive(Eq, Hash, PartialEq, Copy, Clone)]
+pub enum Dir { Up, Down, Left, Right}
+
+pub fn neighbour(pos: usize, dir: &Dir, width: usize, max: usize) -> Option<usize>
 {
     let x = pos % width;
     let y = pos / width;
@@ -543,7 +691,7 @@ fn neighbour(pos: usize, dir: &Dir, width: usize, max: usize) -> Option<usize>
     }
 }
 
-fn opposite(dir: &Dir) -> Dir
+pub fn opposite(dir: &Dir) -> Dir
 {
     match dir
     {
@@ -554,123 +702,38 @@ fn opposite(dir: &Dir) -> Dir
     }
 }
 
-
-
-struct Line
+// What the overlay shows for one cell, in the same layering the cells used to be drawn in:
+// heatmap, then the search (visited cells or a technique), then the solution
+fn overlay_color(i: usize, solver: &Solver, heatmap: &Heatmap) -> Color
 {
-    x0: f32,
-    y0: f32,
-    x1: f32,
-    y1: f32
-}
+    if solver.on_path[i] { return SOLUTION_COLOR; }
 
-fn compute_wall_lines(grid: &Vec<Cell>, grid_width: usize, grid_height: usize, cell_size: f32, offset: (f32, f32)) -> Vec<Line>
-{
-    let mut lines = Vec::new();
+    let base = if heatmap.settings.enabled { heatmap.color(i).unwrap_or(BLANK) } else { BLANK };
 
-    for y_idx in 0..grid_height
+    let search = match &solver.technique
     {
-        let mut x_start: Option<f32> = None;
-
-        for x_idx in 0..grid_width
-        {
-            let i = y_idx * grid_width + x_idx;
-            let cell = &grid[i];
-
-            // First top wall
-            if y_idx == 0 && x_idx == 0
-            {
-                lines.push(Line
-                {
-                    x0: offset.0,
-                    y0: offset.1,
-                    x1: offset.0 + grid_width as f32 * cell_size,
-                    y1: offset.1
-                });
-            }
-
-            if cell.up || y_idx == 0
-            {
-                if x_start.is_none()
-                {
-                    x_start = Some(offset.0 + x_idx as f32 * cell_size);
-                }
-            }
-            else if let Some(x0) = x_start
-            {
-                let x1 = offset.0 + x_idx as f32 * cell_size;
-                let y = offset.1 + y_idx as f32 * cell_size;
-                lines.push(Line { x0: x0.round(), y0: y.round(), x1: x1.round(), y1: y.round() });
-                x_start = None;
-            }
-
-            if y_idx == grid_height - 1 && cell.down
-            {
-                let x = offset.0 + x_idx as f32 * cell_size;
-                let y = offset.1 + (y_idx + 1) as f32 * cell_size;
-                lines.push(Line { x0: x.round(), y0: y.round(), x1: (x + cell_size).round(), y1: y.round() });
-            }
-        }
-
-        if let Some(x0) = x_start
-        {
-            let x1 = offset.0 + grid_width as f32 * cell_size;
-            let y = offset.1 + y_idx as f32 * cell_size;
-            lines.push(Line { x0: x0, y0: y, x1: x1, y1: y });
-        }
-    }
-
-    for x_idx in 0..grid_width
+        Some(technique) if technique.filled[i] => Some(FILLED_COLOR),
+        Some(technique) if technique.done && technique.failed.is_none() => None,
+        Some(technique) if technique.trail[i] > 1 => Some(RETRACED_COLOR),
+        Some(technique) if technique.trail[i] > 0 => Some(TRAIL_COLOR),
+        Some(_) => None,
+        None if !solver.finished && solver.visited[i] => Some(VISITED_COLOR),
+        None => None,
+    };
+
+    match search
     {
-        let mut y_start: Option<f32> = None;
-
-        for y_idx in 0..grid_height
-        {
-            let i = y_idx * grid_width + x_idx;
-            let cell = &grid[i];
-
-            // First Left wall
-            if x_idx == 0 && y_idx == 0
-            {
-                lines.push(Line
-                {
-                    x0: offset.0,
-                    y0: offset.1,
-                    x1: offset.0,
-                    y1: offset.1 + grid_height as f32 * cell_size
-                });
-            }
-
-            if cell.left || x_idx == 0
-            {
-                if y_start.is_none()
-                {
-                    y_start = Some(offset.1 + y_idx as f32 * cell_size);
-                }
-            }
-            else if let Some(y0) = y_start
-            {
-                let y1 = offset.1 + y_idx as f32 * cell_size;
-                let x = offset.0 + x_idx as f32 * cell_size;
-                lines.push(Line { x0: x.round(), y0: y0.round(), x1: x.round(), y1: y1.round() });
-                y_start = None;
-            }
-
-            if x_idx == grid_width - 1 && cell.right
-            {
-                let x = offset.0 + (x_idx + 1) as f32 * cell_size;
-                let y = offset.1 + y_idx as f32 * cell_size;
-                lines.push(Line { x0: x.round(), y0: y.round(), x1: x.round(), y1: (y + cell_size).round() });
-            }
-        }
-
-        if let Some(y0) = y_start
-        {
-            let y1 = offset.1 + grid_height as f32 * cell_size;
-            let x = offset.0 + x_idx as f32 * cell_size;
-            lines.push(Line { x0: x.round(), y0: y0.round(), x1: x.round(), y1: y1.round() });
-        }
+        Some(color) => blend(color, base),
+        None => base,
     }
+}
+
+// top over bottom
+fn blend(top: Color, bottom: Color) -> Color
+{
+    let a = top.a + bottom.a * (1.0 - top.a);
+    if a <= 0.0 { return BLANK; }
 
-    lines
-}
\ No newline at end of file
+    let mix = |t: f32, b: f32| (t * top.a + b * bottom.a * (1.0 - top.a)) / a;
+    Color::new(mix(top.r, bottom.r), mix(top.g, bottom.g), mix(top.b, bottom.b), a)
+}
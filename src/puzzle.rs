@@ -0,0 +1,221 @@
+use macroquad::{prelude::*, rand::gen_range};
+
+use crate::{GridConfig, maze::{Cell, Dir, neighbour}, solver};
+
+pub const MAX_DOORS: usize = 8;
+
+// One hue per key/door pair
+const KEY_COLORS: [Color; MAX_DOORS] =
+[
+    Color::new(0.95, 0.3, 0.3, 1.0),
+    Color::new(0.3, 0.6, 0.95, 1.0),
+    Color::new(0.95, 0.85, 0.2, 1.0),
+    Color::new(0.7, 0.35, 0.95, 1.0),
+    Color::new(0.3, 0.9, 0.5, 1.0),
+    Color::new(0.95, 0.55, 0.15, 1.0),
+    Color::new(0.2, 0.9, 0.9, 1.0),
+    Color::new(0.95, 0.4, 0.75, 1.0),
+];
+pub const COLLECTIBLE_COLOR: Color = Color::new(1.0, 0.95, 0.6, 1.0);
+
+pub fn key_color(id: usize) -> Color
+{
+    KEY_COLORS[id % MAX_DOORS]
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct PuzzleSettings
+{
+    pub doors: usize,
+    pub collectibles: usize,
+}
+
+impl Default for PuzzleSettings
+{
+    fn default() -> Self
+    {
+        PuzzleSettings { doors: 2, collectibles: 3 }
+    }
+}
+
+// Locked edge between cell and its neighbour in dir
+#[derive(Copy, Clone)]
+pub struct Door
+{
+    pub cell: usize,
+    pub dir: Dir,
+    pub other: usize,
+    pub key: usize,
+}
+
+#[derive(Copy, Clone)]
+pub struct Key
+{
+    pub cell: usize,
+    pub id: usize,
+}
+
+#[derive(Default, Clone)]
+pub struct Puzzle
+{
+    pub doors: Vec<Door>,
+    pub keys: Vec<Key>,
+    pub collectibles: Vec<usize>,
+    pub route_length: usize, // Steps of the shortest solution, keys included
+}
+
+impl Puzzle
+{
+    pub fn is_empty(&self) -> bool
+    {
+        self.doors.is_empty() && self.collectibles.is_empty()
+    }
+
+    pub fn door_between(&self, a: usize, b: usize) -> Option<&Door>
+    {
+        self.doors.iter().find(|door| (door.cell == a && door.other == b) || (door.cell == b && door.other == a))
+    }
+
+    // Doors go onto the solution path, each key somewhere it can be fetched with the earlier keys only
+    // Anything that doesn't verify gets retried with one door less
+    pub fn place(settings: PuzzleSettings, start: usize, end: usize, grid: &[Cell], grid_config: &GridConfig) -> Puzzle
+    {
+        let Some(path) = solver::shortest_path(start, end, grid, grid_config) else { return Puzzle::default(); };
+
+        let mut door_count = settings.doors.min(MAX_DOORS).min(path.len().saturating_sub(2) / 2);
+        loop
+        {
+            let mut puzzle = Puzzle::default();
+
+            if place_doors(&mut puzzle, door_count, &path, start, grid, grid_config)
+            && let Some(route) = solver::solve_puzzle(start, end, grid, &puzzle, grid_config)
+            {
+                puzzle.route_length = route.len() - 1;
+                place_collectibles(&mut puzzle, settings.collectibles, start, end, grid, grid_config);
+                return puzzle;
+            }
+
+            if door_count == 0 { return Puzzle::default(); }
+            door_count -= 1;
+        }
+    }
+
+    // visible hides what's in the fog, picked_up hides keys and collectibles already taken
+    pub fn draw(&self, grid_config: &GridConfig, visible: impl Fn(usize) -> bool, picked_up: impl Fn(usize) -> bool)
+    {
+        let cell_size = grid_config.cell_size;
+        let grid_width = grid_config.grid_width;
+        let corner = |i: usize| vec2((i % grid_width) as f32 * cell_size + grid_config.offset.0, (i / grid_width) as f32 * cell_size + grid_config.offset.1);
+
+        for door in self.doors.iter().filter(|door| visible(door.cell) || visible(door.other))
+        {
+            let p = corner(door.cell);
+            let thickness = (cell_size * 0.2).max(3.0);
+            let (x, y, w, h) = match door.dir
+            {
+                Dir::Up => (p.x, p.y - thickness * 0.5, cell_size, thickness),
+                Dir::Down => (p.x, p.y + cell_size - thickness * 0.5, cell_size, thickness),
+                Dir::Left => (p.x - thickness * 0.5, p.y, thickness, cell_size),
+                Dir::Right => (p.x + cell_size - thickness * 0.5, p.y, thickness, cell_size),
+            };
+            draw_rectangle(x, y, w, h, key_color(door.key));
+        }
+
+        for key in self.keys.iter().filter(|key| visible(key.cell) && !picked_up(key.cell))
+        {
+            let p = corner(key.cell) + vec2(cell_size, cell_size) * 0.5;
+            draw_circle(p.x, p.y, cell_size * 0.25, key_color(key.id));
+            draw_circle_lines(p.x, p.y, cell_size * 0.25, 2.0, BLACK);
+        }
+
+        for &cell in self.collectibles.iter().filter(|cell| visible(**cell) && !picked_up(**cell))
+        {
+            let p = corner(cell) + vec2(cell_size, cell_size) * 0.5;
+            draw_poly(p.x, p.y, 4, cell_size * 0.22, 45.0, COLLECTIBLE_COLOR);
+        }
+    }
+}
+
+fn place_doors(puzzle: &mut Puzzle, door_count: usize, path: &[usize], start: usize, grid: &[Cell], grid_config: &GridConfig) -> bool
+{
+    for id in 0..door_count
+    {
+        // Evenly spread along the path, never right at start or end
+        let at = (path.len() - 1) * (id + 1) / (door_count + 1);
+        let (cell, other) = (path[at], path[at + 1]);
+        let Some(dir) = [Dir::Up, Dir::Down, Dir::Left, Dir::Right].into_iter()
+        .find(|dir| neighbour(cell, dir, grid_config.grid_width, grid_config.grid_size) == Some(other)) else { return false; };
+
+        puzzle.doors.push(Door { cell, dir, other, key: id });
+    }
+
+    let on_path: Vec<bool> = (0..grid_config.grid_size).map(|i| path.contains(&i)).collect();
+    for id in 0..door_count
+    {
+        // Keys for doors before this one are held, this and later doors are still closed
+        let reachable = reachable_with_keys(start, grid, &puzzle.doors, id, grid_config);
+        let Some(key_cell) = pick_spot(&reachable, grid, &on_path, puzzle, grid_config) else { return false; };
+
+        puzzle.keys.push(Key { cell: key_cell, id });
+    }
+
+    true
+}
+
+fn place_collectibles(puzzle: &mut Puzzle, amount: usize, start: usize, end: usize, grid: &[Cell], grid_config: &GridConfig)
+{
+    let mut reachable = reachable_with_keys(start, grid, &puzzle.doors, puzzle.doors.len(), grid_config);
+    reachable[start] = false;
+    reachable[end] = false;
+
+    let none = vec![false; grid_config.grid_size];
+    for _ in 0..amount
+    {
+        let Some(cell) = pick_spot(&reachable, grid, &none, puzzle, grid_config) else { return; };
+        puzzle.collectibles.push(cell);
+    }
+}
+
+// Prefers dead ends away from the solution, then any free cell
+fn pick_spot(reachable: &[bool], grid: &[Cell], avoid: &[bool], puzzle: &Puzzle, grid_config: &GridConfig) -> Option<usize>
+{
+    let taken = |cell: usize| puzzle.keys.iter().any(|key| key.cell == cell) || puzzle.collectibles.contains(&cell);
+    let free: Vec<usize> = (0..grid_config.grid_size).filter(|&cell| reachable[cell] && !taken(cell)).collect();
+
+    let dead_ends: Vec<usize> = free.iter().copied().filter(|&cell| !avoid[cell] && open_sides(&grid[cell]) == 1).collect();
+    let off_path: Vec<usize> = free.iter().copied().filter(|&cell| !avoid[cell]).collect();
+
+    [dead_ends, off_path, free].into_iter()
+    .find(|candidates| !candidates.is_empty())
+    .map(|candidates| candidates[gen_range(0, candidates.len())])
+}
+
+fn open_sides(cell: &Cell) -> usize
+{
+    [cell.up, cell.down, cell.left, cell.right].iter().filter(|wall| !**wall).count()
+}
+
+// Flood fill where doors with an id below opened_below count as open
+fn reachable_with_keys(start: usize, grid: &[Cell], doors: &[Door], opened_below: usize, grid_config: &GridConfig) -> Vec<bool>
+{
+    let mut reachable = vec![false; grid_config.grid_size];
+    let mut stack = vec![start];
+    reachable[start] = true;
+
+    while let Some(cell) = stack.pop()
+    {
+        for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+        {
+            if grid[cell].has_wall(&dir) { continue; }
+            let Some(next) = neighbour(cell, &dir, grid_config.grid_width, grid_config.grid_size) else { continue; };
+
+            let locked = doors.iter().any(|door| door.key >= opened_below && ((door.cell == cell && door.other == next) || (door.cell == next && door.other == cell)));
+            if locked || reachable[next] { continue; }
+
+            reachable[next] = true;
+            stack.push(next);
+        }
+    }
+
+    reachable
+}
@@ -0,0 +1,293 @@
+use std::{error::Error, fs::File, io::BufWriter, path::{Path, PathBuf}};
+
+use image::{Delay, Frame, Rgba, RgbaImage, codecs::gif::{GifEncoder, Repeat}};
+use macroquad::color::{Color, WHITE};
+
+use crate::{GridConfig, generator::Phase, maze::{self, Dir, Maze}, puzzle, techniques::SolverAlgorithm};
+
+const BACKGROUND: Rgba<u8> = Rgba([42, 42, 42, 255]);
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum RecordFormat
+{
+    Gif,
+    Apng,
+    PngSequence, // Path is a folder, frames get numbered
+}
+
+#[derive(Copy, Clone)]
+pub struct RecordSettings
+{
+    pub format: RecordFormat,
+    pub width: u32,
+    pub height: u32,
+    pub steps_per_frame: usize,
+    pub frame_delay_ms: u32,
+}
+
+impl Default for RecordSettings
+{
+    fn default() -> Self
+    {
+        RecordSettings
+        {
+            format: RecordFormat::Gif,
+            width: 512,
+            height: 512,
+            steps_per_frame: 1,
+            frame_delay_ms: 40,
+        }
+    }
+}
+
+// Renders maze steps offscreen (independent of the window) and writes them out as they come
+// APNG needs the frame count before the first frame, so its frames wait on disk in a folder next to the file
+pub struct Recorder
+{
+    settings: RecordSettings,
+    path: PathBuf,
+    gif: Option<GifEncoder<BufWriter<File>>>,
+    frame_folder: PathBuf, // The output itself for png sequences
+    frame_count: usize,
+    step_count: usize,
+}
+
+impl Recorder
+{
+    pub fn new(settings: RecordSettings, path: &Path) -> Result<Self, Box<dyn Error>>
+    {
+        let gif = match settings.format
+        {
+            RecordFormat::Gif =>
+            {
+                let mut encoder = GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), 10);
+                encoder.set_repeat(Repeat::Infinite)?;
+                Some(encoder)
+            },
+            RecordFormat::PngSequence => { std::fs::create_dir_all(path)?; None },
+            RecordFormat::Apng => { std::fs::create_dir_all(apng_folder(path))?; None },
+        };
+
+        Ok(Recorder
+        {
+            settings: RecordSettings { steps_per_frame: settings.steps_per_frame.max(1), ..settings },
+            path: path.to_path_buf(),
+            gif,
+            frame_folder: if settings.format == RecordFormat::Apng { apng_folder(path) } else { path.to_path_buf() },
+            frame_count: 0,
+            step_count: 0,
+        })
+    }
+
+    // Called after every step, only every n-th one becomes a frame
+    pub fn step(&mut self, maze: &Maze, grid_config: &GridConfig) -> Result<(), Box<dyn Error>>
+    {
+        self.step_count += 1;
+        if !self.step_count.is_multiple_of(self.settings.steps_per_frame) && maze.is_animating() { return Ok(()); }
+
+        self.capture(maze, grid_config)
+    }
+
+    pub fn capture(&mut self, maze: &Maze, grid_config: &GridConfig) -> Result<(), Box<dyn Error>>
+    {
+        let frame = render_frame(maze, grid_config, self.settings.width, self.settings.height);
+
+        match self.settings.format
+        {
+            RecordFormat::Gif =>
+            {
+                if let Some(encoder) = self.gif.as_mut()
+                {
+                    let delay = Delay::from_numer_denom_ms(self.settings.frame_delay_ms, 1);
+                    encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay))?;
+                }
+            },
+            RecordFormat::Apng | RecordFormat::PngSequence =>
+            {
+                frame.save(self.frame_folder.join(frame_name(self.frame_count)))?;
+            }
+        }
+
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    // Returns the amount of written frames
+    pub fn finish(self) -> Result<usize, Box<dyn Error>>
+    {
+        if self.settings.format == RecordFormat::Apng
+        {
+            // Read back one at a time, so only a single frame is ever in memory
+            if self.frame_count > 0
+            {
+                let mut encoder = png::Encoder::new(BufWriter::new(File::create(&self.path)?), self.settings.width, self.settings.height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(self.frame_count as u32, 0)?;
+                encoder.set_frame_delay(self.settings.frame_delay_ms.min(u16::MAX as u32) as u16, 1000)?;
+
+                let mut writer = encoder.write_header()?;
+                for i in 0..self.frame_count
+                {
+                    let frame = image::open(self.frame_folder.join(frame_name(i)))?.to_rgba8();
+                    writer.write_image_data(frame.as_raw())?;
+                }
+                writer.finish()?;
+            }
+            std::fs::remove_dir_all(&self.frame_folder)?;
+        }
+
+        // Dropping the gif encoder writes the trailer
+        Ok(self.frame_count)
+    }
+
+    pub fn frame_count(&self) -> usize
+    {
+        self.frame_count
+    }
+}
+
+fn apng_folder(path: &Path) -> PathBuf
+{
+    let mut folder = path.as_os_str().to_owned();
+    folder.push(".frames");
+    PathBuf::from(folder)
+}
+
+fn frame_name(frame: usize) -> String
+{
+    format!("frame_{:05}.png", frame)
+}
+
+// Same layering as Maze::draw, just into an image
+pub fn render_frame(maze: &Maze, grid_config: &GridConfig, width: u32, height: u32) -> RgbaImage
+{
+    let mut image = RgbaImage::from_pixel(width, height, BACKGROUND);
+
+    let grid_width = grid_config.grid_width;
+    let grid_height = grid_config.grid_height;
+
+    let cell_size = (width as f32 / grid_width as f32).min(height as f32 / grid_height as f32);
+    let offset_x = (width as f32 - cell_size * grid_width as f32) * 0.5;
+    let offset_y = (height as f32 - cell_size * grid_height as f32) * 0.5;
+
+    let fill_cell = |image: &mut RgbaImage, i: usize, color: Color|
+    {
+        let x = (i % grid_width) as f32 * cell_size + offset_x;
+        let y = (i / grid_width) as f32 * cell_size + offset_y;
+        fill_rect(image, x, y, cell_size, cell_size, color);
+    };
+
+    if let Some(generator) = &maze.generator
+    {
+        for i in 0..generator.grid.len()
+        {
+            if generator.path[i] && generator.phase != Phase::Carving { fill_cell(&mut image, i, maze::PATH_COLOR); }
+            else if generator.visited[i] { fill_cell(&mut image, i, maze::CARVED_COLOR); }
+        }
+        for i in generator.frontier() { fill_cell(&mut image, i, maze::FRONTIER_COLOR); }
+        if let Some(i) = generator.current { fill_cell(&mut image, i, maze::CURRENT_COLOR); }
+    }
+    else
+    {
+        if maze.heatmap.settings.enabled
+        {
+            for i in 0..maze.grid.len()
+            {
+                if let Some(color) = maze.heatmap.color(i) { fill_cell(&mut image, i, color); }
+            }
+        }
+
+        if let Some(technique) = &maze.solver.technique
+        {
+            for i in 0..maze.grid.len()
+            {
+                if technique.filled[i] { fill_cell(&mut image, i, maze::FILLED_COLOR); }
+                else if technique.trail[i] > 1 && !technique.done { fill_cell(&mut image, i, maze::RETRACED_COLOR); }
+                else if technique.trail[i] > 0 && !technique.done { fill_cell(&mut image, i, maze::TRAIL_COLOR); }
+            }
+            if !technique.done && technique.algorithm != SolverAlgorithm::DeadEndFilling { fill_cell(&mut image, technique.pos, maze::WALKER_COLOR); }
+        }
+        else if !maze.solver.finished
+        {
+            for i in 0..maze.grid.len()
+            {
+                if maze.solver.visited[i] { fill_cell(&mut image, i, maze::VISITED_COLOR); }
+            }
+        }
+        for &i in maze.solver.final_path.iter() { fill_cell(&mut image, i, maze::SOLUTION_COLOR); }
+    }
+
+    fill_cell(&mut image, maze.start, maze::START_COLOR);
+    fill_cell(&mut image, maze.end, maze::END_COLOR);
+    for &i in maze.waypoint_stops().iter() { fill_cell(&mut image, i, maze::WAYPOINT_COLOR); }
+
+    let grid = maze.generator.as_ref().map_or(&maze.grid, |generator| &generator.grid);
+    let wall = (cell_size / 20.0).max(1.0);
+
+    for (i, cell) in grid.iter().enumerate()
+    {
+        let x = (i % grid_width) as f32 * cell_size + offset_x;
+        let y = (i / grid_width) as f32 * cell_size + offset_y;
+
+        if cell.up { fill_rect(&mut image, x - wall * 0.5, y - wall * 0.5, cell_size + wall, wall, WHITE); }
+        if cell.down { fill_rect(&mut image, x - wall * 0.5, y + cell_size - wall * 0.5, cell_size + wall, wall, WHITE); }
+        if cell.left { fill_rect(&mut image, x - wall * 0.5, y - wall * 0.5, wall, cell_size + wall, WHITE); }
+        if cell.right { fill_rect(&mut image, x + cell_size - wall * 0.5, y - wall * 0.5, wall, cell_size + wall, WHITE); }
+    }
+
+    if maze.generator.is_none()
+    {
+        let corner = |i: usize| ((i % grid_width) as f32 * cell_size + offset_x, (i / grid_width) as f32 * cell_size + offset_y);
+        let door = (cell_size * 0.2).max(2.0);
+
+        for d in maze.puzzle.doors.iter()
+        {
+            let (x, y) = corner(d.cell);
+            let color = puzzle::key_color(d.key);
+            match d.dir
+            {
+                Dir::Up => fill_rect(&mut image, x, y - door * 0.5, cell_size, door, color),
+                Dir::Down => fill_rect(&mut image, x, y + cell_size - door * 0.5, cell_size, door, color),
+                Dir::Left => fill_rect(&mut image, x - door * 0.5, y, door, cell_size, color),
+                Dir::Right => fill_rect(&mut image, x + cell_size - door * 0.5, y, door, cell_size, color),
+            }
+        }
+
+        // Squares instead of circles/diamonds, small enough that it doesn't matter
+        for key in maze.puzzle.keys.iter()
+        {
+            let (x, y) = corner(key.cell);
+            fill_rect(&mut image, x + cell_size * 0.25, y + cell_size * 0.25, cell_size * 0.5, cell_size * 0.5, puzzle::key_color(key.id));
+        }
+        for &cell in maze.puzzle.collectibles.iter()
+        {
+            let (x, y) = corner(cell);
+            fill_rect(&mut image, x + cell_size * 0.35, y + cell_size * 0.35, cell_size * 0.3, cell_size * 0.3, puzzle::COLLECTIBLE_COLOR);
+        }
+    }
+
+    image
+}
+
+// Alpha blended, so the see-through overlay colors match the window
+fn fill_rect(image: &mut RgbaImage, x: f32, y: f32, w: f32, h: f32, color: Color)
+{
+    let x0 = x.round().max(0.0) as u32;
+    let y0 = y.round().max(0.0) as u32;
+    let x1 = ((x + w).round().max(0.0) as u32).min(image.width());
+    let y1 = ((y + h).round().max(0.0) as u32).min(image.height());
+
+    for py in y0..y1
+    {
+        for px in x0..x1
+        {
+            let pixel = image.get_pixel_mut(px, py);
+            for c in 0..3
+            {
+                let src = [color.r, color.g, color.b][c] * 255.0;
+                pixel[c] = (src * color.a + pixel[c] as f32 * (1.0 - color.a)).round() as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use macroquad::prelude::*;
+
+use crate::{GridConfig, maze::Cell};
+
+const MAX_TEXTURE_SIZE: f32 = 8192.0;
+const WALL_THICKNESS: f32 = 2.0;
+
+// Wall segment in cell units, neighbouring walls merged into one
+pub struct Line
+{
+    pub x0: f32,
+    pub y0: f32,
+    pub x1: f32,
+    pub y1: f32,
+}
+
+// Instead of drawing every cell every frame, walls get rendered into a texture once per change
+// and the cell colors live in a texture with one pixel per cell, where only changed cells get rewritten
+pub struct MazeRenderer
+{
+    walls: Option<RenderTarget>,
+    walls_scale: f32, // Pixels per cell in the wall texture
+    walls_dirty: bool,
+    overlay_image: Image,
+    overlay: Option<Texture2D>,
+    overlay_dirty: bool, // Everything needs to be rewritten
+}
+
+impl MazeRenderer
+{
+    pub fn new() -> Self
+    {
+        MazeRenderer
+        {
+            walls: None,
+            walls_scale: 0.0,
+            walls_dirty: true,
+            overlay_image: Image::empty(),
+            overlay: None,
+            overlay_dirty: true,
+        }
+    }
+
+    pub fn invalidate_walls(&mut self)
+    {
+        self.walls_dirty = true;
+    }
+
+    pub fn invalidate_overlay(&mut self)
+    {
+        self.overlay_dirty = true;
+    }
+
+    pub fn draw_walls(&mut self, lines: &[Line], grid_config: &GridConfig)
+    {
+        let (grid_width, grid_height) = (grid_config.grid_width as f32, grid_config.grid_height as f32);
+
+        // Big grids get a smaller texture, stretched back up
+        let scale = grid_config.cell_size.min(MAX_TEXTURE_SIZE / grid_width.max(grid_height)).max(1.0);
+        let margin = WALL_THICKNESS;
+
+        if self.walls_dirty || self.walls.is_none() || scale != self.walls_scale
+        {
+            let width = (grid_width * scale + margin * 2.0).ceil() as u32;
+            let height = (grid_height * scale + margin * 2.0).ceil() as u32;
+
+            let target = match self.walls.take()
+            {
+                Some(target) if target.texture.width() as u32 == width && target.texture.height() as u32 == height => target,
+                _ => render_target(width, height),
+            };
+            target.texture.set_filter(FilterMode::Linear);
+
+            let mut camera = Camera2D::from_display_rect(Rect::new(0.0, 0.0, width as f32, height as f32));
+            camera.render_target = Some(target.clone());
+
+            push_camera_state();
+            set_camera(&camera);
+            clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
+            for line in lines
+            {
+                let (x0, y0) = (line.x0 * scale + margin, line.y0 * scale + margin);
+                let (x1, y1) = (line.x1 * scale + margin, line.y1 * scale + margin);
+                draw_rectangle(x0 - WALL_THICKNESS * 0.5, y0 - WALL_THICKNESS * 0.5, x1 - x0 + WALL_THICKNESS, y1 - y0 + WALL_THICKNESS, WHITE);
+            }
+            pop_camera_state();
+
+            self.walls = Some(target);
+            self.walls_scale = scale;
+            self.walls_dirty = false;
+        }
+
+        let Some(target) = &self.walls else { return; };
+        let to_screen = grid_config.cell_size / scale;
+        let size = vec2(target.texture.width(), target.texture.height()) * to_screen;
+
+        draw_texture_ex(&target.texture, grid_config.offset.0 - margin * to_screen, grid_config.offset.1 - margin * to_screen, WHITE, DrawTextureParams
+        {
+            dest_size: Some(size),
+            flip_y: true, // Render targets come out upside down
+            ..Default::default()
+        });
+    }
+
+    // changed are the cells that need a new color, unless everything gets rewritten anyway
+    pub fn draw_overlay(&mut self, changed: &[usize], cell_color: impl Fn(usize) -> Color, grid_config: &GridConfig)
+    {
+        let (grid_width, grid_height) = (grid_config.grid_width, grid_config.grid_height);
+
+        if self.overlay_image.width() != grid_width || self.overlay_image.height() != grid_height
+        {
+            self.overlay_image = Image::gen_image_color(grid_width as u16, grid_height as u16, BLANK);
+            self.overlay = None;
+            self.overlay_dirty = true;
+        }
+
+        if self.overlay_dirty
+        {
+            for i in 0..grid_config.grid_size
+            {
+                self.overlay_image.set_pixel((i % grid_width) as u32, (i / grid_width) as u32, cell_color(i));
+            }
+        }
+        else
+        {
+            for &i in changed.iter().filter(|&&i| i < grid_config.grid_size)
+            {
+                self.overlay_image.set_pixel((i % grid_width) as u32, (i / grid_width) as u32, cell_color(i));
+            }
+        }
+
+        match &self.overlay
+        {
+            Some(texture) if self.overlay_dirty || !changed.is_empty() => texture.update(&self.overlay_image),
+            Some(_) => {},
+            None =>
+            {
+                let texture = Texture2D::from_image(&self.overlay_image);
+                texture.set_filter(FilterMode::Nearest);
+                self.overlay = Some(texture);
+            }
+        }
+        self.overlay_dirty = false;
+
+        let Some(texture) = &self.overlay else { return; };
+        draw_texture_ex(texture, grid_config.offset.0, grid_config.offset.1, WHITE, DrawTextureParams
+        {
+            dest_size: Some(vec2(grid_width as f32, grid_height as f32) * grid_config.cell_size),
+            ..Default::default()
+        });
+    }
+}
+
+impl Default for MazeRenderer
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+// Straight wall runs, so a long wall is one line instead of one per cell
+// Borders come from the cells as well, so openings in them stay open
+pub fn compute_wall_lines(grid: &[Cell], grid_width: usize, grid_height: usize) -> Vec<Line>
+{
+    let mut lines = Vec::new();
+
+    // Horizontal walls, y is the boundary row (0 to grid_height)
+    for y in 0..=grid_height
+    {
+        let mut run_start: Option<usize> = None;
+        for x in 0..=grid_width
+        {
+            let wall = x < grid_width && if y < grid_height { grid[y * grid_width + x].up } else { grid[(y - 1) * grid_width + x].down };
+
+            match (wall, run_start)
+            {
+                (true, None) => run_start = Some(x),
+                (false, Some(x0)) =>
+                {
+                    lines.push(Line { x0: x0 as f32, y0: y as f32, x1: x as f32, y1: y as f32 });
+                    run_start = None;
+                },
+                _ => {},
+            }
+        }
+    }
+
+    // Vertical walls, x is the boundary column
+    for x in 0..=grid_width
+    {
+        let mut run_start: Option<usize> = None;
+        for y in 0..=grid_height
+        {
+            let wall = y < grid_height && if x < grid_width { grid[y * grid_width + x].left } else { grid[y * grid_width + x - 1].right };
+
+            match (wall, run_start)
+            {
+                (true, None) => run_start = Some(y),
+                (false, Some(y0)) =>
+                {
+                    lines.push(Line { x0: x as f32, y0: y0 as f32, x1: x as f32, y1: y as f32 });
+                    run_start = None;
+                },
+                _ => {},
+            }
+        }
+    }
+
+    lines
+}
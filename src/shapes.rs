@@ -0,0 +1,95 @@
+use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_4, TAU};
+
+use macroquad::prelude::*;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ShapeTool
+{
+    Freehand,
+    Line,
+    Rectangle,
+    Ellipse,
+    Polyline,
+    Bezier,
+}
+
+impl ShapeTool
+{
+    pub const ALL: [ShapeTool; 6] = [ShapeTool::Freehand, ShapeTool::Line, ShapeTool::Rectangle, ShapeTool::Ellipse, ShapeTool::Polyline, ShapeTool::Bezier];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            ShapeTool::Freehand => "Freehand",
+            ShapeTool::Line => "Line",
+            ShapeTool::Rectangle => "Rectangle",
+            ShapeTool::Ellipse => "Ellipse",
+            ShapeTool::Polyline => "Polyline",
+            ShapeTool::Bezier => "Bezier",
+        }
+    }
+
+    pub fn hint(self) -> &'static str
+    {
+        match self
+        {
+            ShapeTool::Freehand => "",
+            ShapeTool::Line | ShapeTool::Rectangle | ShapeTool::Ellipse => "Drag to draw, Shift for 45°",
+            ShapeTool::Polyline => "Click points, right click or Enter to finish",
+            ShapeTool::Bezier => "Click start, end, then the bend",
+        }
+    }
+}
+
+// Turns to to the nearest multiple of 45° around from
+// Diagonals keep their projected length, so rectangles become squares and ellipses circles
+pub fn constrain(from: Vec2, to: Vec2) -> Vec2
+{
+    let delta = to - from;
+    if delta == Vec2::ZERO { return to; }
+
+    let angle = (delta.y.atan2(delta.x) / FRAC_PI_4).round() * FRAC_PI_4;
+    let dir = vec2(angle.cos(), angle.sin());
+    let along = delta.dot(dir);
+
+    // Keep the exact axis, cos and sin don't give clean zeros
+    let offset = if dir.x.abs() < 0.5 { vec2(0.0, along * dir.y.signum()) }
+        else if dir.y.abs() < 0.5 { vec2(along * dir.x.signum(), 0.0) }
+        else { vec2(along * dir.x.signum(), along * dir.y.signum()) * FRAC_1_SQRT_2 };
+
+    from + offset
+}
+
+// Points along the shape, drawn as connected segments
+// Bezier points are start, end and then the control point
+pub fn outline(tool: ShapeTool, points: &[Vec2]) -> Vec<Vec2>
+{
+    match (tool, points)
+    {
+        (ShapeTool::Rectangle, &[a, b]) => vec![a, vec2(b.x, a.y), b, vec2(a.x, b.y), a],
+        (ShapeTool::Ellipse, &[a, b]) =>
+        {
+            let center = (a + b) * 0.5;
+            let radius = (b - a).abs() * 0.5;
+            let segments = (radius.x + radius.y).clamp(16.0, 256.0) as usize;
+
+            (0..=segments).map(|i|
+            {
+                let t = i as f32 / segments as f32 * TAU;
+                center + vec2(t.cos(), t.sin()) * radius
+            }).collect()
+        },
+        (ShapeTool::Bezier, &[start, end, control]) =>
+        {
+            let segments = ((control - start).length() + (end - control).length()).clamp(8.0, 256.0) as usize;
+
+            (0..=segments).map(|i|
+            {
+                let t = i as f32 / segments as f32;
+                start * (1.0 - t) * (1.0 - t) + control * 2.0 * (1.0 - t) * t + end * t * t
+            }).collect()
+        },
+        _ => points.to_vec(),
+    }
+}
@@ -1,6 +1,6 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque, hash_map::Entry};
 
-use crate::{GridConfig, maze::Cell};
+use crate::{GridConfig, maze::{Cell, Dir, neighbour, opposite}, puzzle::Puzzle, techniques::{SolverAlgorithm, Technique}};
 
 pub struct Solver // Quite inefficient
 {
@@ -12,7 +12,19 @@ pub struct Solver // Quite inefficient
     pub path_pos: usize,
     pub final_path: Vec<usize>,
     pub found: bool,
-    pub finished: bool
+    pub finished: bool,
+    pub steps: usize, // Since the last redo, for stepping back
+    pub origin: usize,
+    pub stops: Vec<usize>, // Waypoints in visiting order, the last one is the end
+    pub leg: usize,        // Which stop is being searched for right now
+    pub algorithm: SolverAlgorithm,
+    pub technique: Option<Technique>, // Set for everything but BFS
+    pub on_path: Vec<bool>,
+    pub changed: Vec<usize>, // Cells that look different since the renderer last looked
+    pub redraw_all: bool,
+    pub puzzle: Puzzle,
+    held_keys: u32,              // Picked up at the stops reached so far
+    walled: Option<Vec<Cell>>,   // The grid with the still locked doors as walls, None without any
 }
 
 impl Solver
@@ -35,16 +47,88 @@ impl Solver
             path_pos: end,
             final_path: Vec::new(),
             found: false,
-            finished: false
+            finished: false,
+            steps: 0,
+            origin: start,
+            stops: vec![end],
+            leg: 0,
+            algorithm: SolverAlgorithm::Bfs,
+            technique: None,
+            on_path: vec![false; grid_config.grid_size],
+            changed: Vec::new(),
+            redraw_all: true,
+            puzzle: Puzzle::default(),
+            held_keys: 0,
+            walled: None,
         }
     }
 
-    pub fn step(&mut self, grid: &Vec<Cell>, grid_config: &GridConfig)
+    // Search until the end is found, then walk the path back
+    pub fn advance(&mut self, grid: &[Cell], grid_config: &GridConfig)
+    {
+        if self.finished { return; }
+
+        // Searching uses the walled grid, a new leg builds its own from the real one
+        let leg = self.leg;
+        let walled = self.walled.take();
+        let search_grid = walled.as_deref().unwrap_or(grid);
+
+        if let Some(technique) = self.technique.as_mut()
+        {
+            technique.step(search_grid, grid_config);
+            self.changed.append(&mut technique.changed);
+            if technique.done
+            {
+                if let Some(reason) = technique.failed { println!("{}: {}", technique.algorithm.name(), reason); }
+                else
+                {
+                    for &cell in technique.route.iter() { self.on_path[cell] = true; }
+                    self.final_path.extend(technique.route.iter().copied());
+                }
+
+                self.redraw_all = true; // The trail goes away
+                if technique.failed.is_some() { self.finished = true; }
+                else { self.next_leg(grid); }
+            }
+        }
+        else if !self.found
+        {
+            self.step(search_grid, grid_config);
+        }
+        else
+        {
+            self.reconstruction_step(grid);
+        }
+        self.steps += 1;
+        if self.leg == leg { self.walled = walled; }
+    }
+
+    pub fn solve(&mut self, grid: &[Cell], grid_config: &GridConfig)
+    {
+        while !self.finished
+        {
+            self.advance(grid, grid_config);
+        }
+    }
+
+    // The search is deterministic, so replaying all but the last step is enough
+    pub fn step_back(&mut self, grid: &[Cell], grid_config: &GridConfig)
+    {
+        let target = self.steps.saturating_sub(1);
+        self.redo_route(self.origin, self.stops.clone(), grid);
+
+        while self.steps < target
+        {
+            self.advance(grid, grid_config);
+        }
+    }
+
+    pub fn step(&mut self, grid: &[Cell], grid_config: &GridConfig)
     {
         // if self.found { return; }
 
         let cell_option = self.queue.pop_front();
-        if cell_option.is_none() { self.found = true; self.finished = true; println!("Error?"); return; }
+        if cell_option.is_none() { self.found = true; self.finished = true; self.redraw_all = true; println!("Error?"); return; }
         let cell = cell_option.unwrap();
 
         let neighbours = solver_sides(cell, grid_config.grid_width, grid_config.grid_height, grid);
@@ -52,6 +136,7 @@ impl Solver
         for i in viable
         {
             self.visited[i] = true;
+            self.changed.push(i);
             self.path[i] = Some(cell);
             if i == self.end
             {
@@ -63,18 +148,56 @@ impl Solver
         }
     }
 
-    pub fn reconstruction_step(&mut self)
+    // final_path keeps growing over all legs
+    pub fn reconstruction_step(&mut self, grid: &[Cell])
     {
         if let Some(new_pos) = self.path[self.path_pos]
         {
             self.path_pos = new_pos;
             self.final_path.push(self.path_pos);
+            self.on_path[self.path_pos] = true;
+            self.changed.push(self.path_pos);
+        }
+        else { self.next_leg(grid); }
+    }
+
+    fn next_leg(&mut self, grid: &[Cell])
+    {
+        self.redraw_all = true;
+        if self.leg + 1 < self.stops.len()
+        {
+            self.leg += 1;
+            self.start_leg(self.end, self.stops[self.leg], grid);
         }
         else { self.finished = true; }
     }
 
-    pub fn redo(&mut self, start: usize, end: usize)
+    pub fn redo(&mut self, start: usize, end: usize, grid: &[Cell])
+    {
+        self.redo_route(start, vec![end], grid);
+    }
+
+    // Searches start -> stops[0] -> stops[1] -> ... one leg after another
+    pub fn redo_route(&mut self, start: usize, stops: Vec<usize>, grid: &[Cell])
+    {
+        self.origin = start;
+        self.stops = stops;
+        self.leg = 0;
+        self.final_path.clear();
+        self.on_path.fill(false);
+        self.changed.clear();
+        self.redraw_all = true;
+        self.finished = false;
+        self.steps = 0;
+        self.held_keys = 0;
+        self.start_leg(start, self.stops[0], grid);
+    }
+
+    fn start_leg(&mut self, start: usize, end: usize, grid: &[Cell])
     {
+        for key in self.puzzle.keys.iter().filter(|key| key.cell == start) { self.held_keys |= 1 << key.id; }
+        self.walled = self.wall_locked_doors(grid);
+
         self.queue.clear(); // Cleared before, but to be sure
         self.queue.push_back(start);
         self.visited.fill(false);
@@ -83,13 +206,41 @@ impl Solver
         self.end = end;
         self.path_pos = end;
         self.path.fill(None);
-        self.final_path.clear();
-        self.found = false;
-        self.finished = false;
+        self.found = start == end; // Nothing to search, the reconstruction moves on right away
+        self.technique = (self.algorithm != SolverAlgorithm::Bfs).then(|| Technique::new(self.algorithm, start, end, self.visited.len()));
+    }
+
+    fn wall_locked_doors(&self, grid: &[Cell]) -> Option<Vec<Cell>>
+    {
+        let mut locked = self.puzzle.doors.iter().filter(|door| self.held_keys & (1 << door.key) == 0).peekable();
+        locked.peek()?;
+
+        let mut walled = grid.to_vec();
+        for door in locked
+        {
+            walled[door.cell].set_wall(&door.dir, true);
+            walled[door.other].set_wall(&opposite(&door.dir), true);
+        }
+        Some(walled)
+    }
+}
+
+// The cells where solve_puzzle's route picks up each key, in order
+// Used as stops so the animated solver fetches the keys before heading through their doors
+pub fn key_stops(start: usize, end: usize, grid: &[Cell], puzzle: &Puzzle, grid_config: &GridConfig) -> Vec<usize>
+{
+    if puzzle.doors.is_empty() { return Vec::new(); }
+    let Some(route) = solve_puzzle(start, end, grid, puzzle, grid_config) else { return Vec::new(); };
+
+    let mut stops: Vec<usize> = Vec::new();
+    for cell in route
+    {
+        if puzzle.keys.iter().any(|key| key.cell == cell) && cell != start && !stops.contains(&cell) { stops.push(cell); }
     }
+    stops
 }
 
-fn solver_sides(pos: usize, width: usize, height: usize, grid: &Vec<Cell>) -> Vec<usize>
+fn solver_sides(pos: usize, width: usize, height: usize, grid: &[Cell]) -> Vec<usize>
 {
     let mut neighbours = Vec::new();
     let x = pos % width;
@@ -102,4 +253,234 @@ fn solver_sides(pos: usize, width: usize, height: usize, grid: &Vec<Cell>) -> Ve
     if y + 1 < height && !cell.down { neighbours.push(pos + width); }
 
     neighbours
-}
\ No newline at end of file
+}
+
+// Plain BFS from one cell, None for unreachable cells
+pub fn distances(start: usize, grid: &[Cell], grid_config: &GridConfig) -> Vec<Option<usize>>
+{
+    let mut distance = vec![None; grid_config.grid_size];
+    let mut queue = VecDeque::new();
+
+    distance[start] = Some(0);
+    queue.push_back(start);
+
+    while let Some(cell) = queue.pop_front()
+    {
+        let d = distance[cell].unwrap_or(0);
+        for next in solver_sides(cell, grid_config.grid_width, grid_config.grid_height, grid)
+        {
+            if distance[next].is_none()
+            {
+                distance[next] = Some(d + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    distance
+}
+
+// Shortest path between two cells, both included
+pub fn shortest_path(start: usize, end: usize, grid: &[Cell], grid_config: &GridConfig) -> Option<Vec<usize>>
+{
+    let mut previous = vec![None; grid_config.grid_size];
+    let mut visited = vec![false; grid_config.grid_size];
+    let mut queue = VecDeque::new();
+
+    visited[start] = true;
+    queue.push_back(start);
+
+    while let Some(cell) = queue.pop_front()
+    {
+        if cell == end
+        {
+            let mut path = vec![end];
+            let mut pos = end;
+            while let Some(prev) = previous[pos]
+            {
+                path.push(prev);
+                pos = prev;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for next in solver_sides(cell, grid_config.grid_width, grid_config.grid_height, grid)
+        {
+            if !visited[next]
+            {
+                visited[next] = true;
+                previous[next] = Some(cell);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+// BFS over (cell, held keys), since a cell can be worth revisiting once more keys are held
+// Returns the route, which might walk back and forth to fetch keys
+pub fn solve_puzzle(start: usize, end: usize, grid: &[Cell], puzzle: &Puzzle, grid_config: &GridConfig) -> Option<Vec<usize>>
+{
+    let key_at: HashMap<usize, u32> = puzzle.keys.iter().map(|key| (key.cell, 1 << key.id)).collect();
+    let pick_up = |cell: usize, keys: u32| keys | key_at.get(&cell).copied().unwrap_or(0);
+
+    let start_state = (start, pick_up(start, 0));
+    let mut previous: HashMap<(usize, u32), (usize, u32)> = HashMap::new();
+    let mut queue = VecDeque::new();
+    previous.insert(start_state, start_state);
+    queue.push_back(start_state);
+
+    while let Some((cell, keys)) = queue.pop_front()
+    {
+        if cell == end
+        {
+            let mut route = vec![cell];
+            let mut state = (cell, keys);
+            while state != start_state
+            {
+                state = previous[&state];
+                route.push(state.0);
+            }
+            route.reverse();
+            return Some(route);
+        }
+
+        for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+        {
+            if grid[cell].has_wall(&dir) { continue; }
+            let Some(next) = neighbour(cell, &dir, grid_config.grid_width, grid_config.grid_size) else { continue; };
+
+            if let Some(door) = puzzle.door_between(cell, next)
+            && keys & (1 << door.key) == 0
+            {
+                continue;
+            }
+
+            let state = (next, pick_up(next, keys));
+            if let Entry::Vacant(entry) = previous.entry(state)
+            {
+                entry.insert((cell, keys));
+                queue.push_back(state);
+            }
+        }
+    }
+
+    None
+}
+
+// Visiting order for the waypoints with the shortest total route from start to end
+// Held-Karp over the distances between all stops, fine for a handful of waypoints
+pub fn shortest_tour(start: usize, waypoints: &[usize], end: usize, grid: &[Cell], grid_config: &GridConfig) -> Vec<usize>
+{
+    let n = waypoints.len();
+    if n < 2 { return waypoints.to_vec(); }
+
+    let from_start = distances(start, grid, grid_config);
+    let from_waypoint: Vec<Vec<Option<usize>>> = waypoints.iter().map(|&w| distances(w, grid, grid_config)).collect();
+    let add = |a: Option<usize>, b: Option<usize>| Some(a? + b?);
+
+    // best[mask][last] = shortest way from start through mask, ending at waypoint last
+    let mut best = vec![vec![None; n]; 1 << n];
+    let mut previous = vec![vec![0; n]; 1 << n];
+    for (i, &w) in waypoints.iter().enumerate()
+    {
+        best[1 << i][i] = from_start[w];
+    }
+
+    for mask in 1..(1usize << n)
+    {
+        for last in 0..n
+        {
+            let Some(length) = best[mask][last] else { continue; };
+
+            for next in (0..n).filter(|next| mask & (1 << next) == 0)
+            {
+                let Some(total) = add(Some(length), from_waypoint[last][waypoints[next]]) else { continue; };
+                let slot = &mut best[mask | (1 << next)][next];
+                if slot.is_none_or(|old| total < old)
+                {
+                    *slot = Some(total);
+                    previous[mask | (1 << next)][next] = last;
+                }
+            }
+        }
+    }
+
+    let full = (1 << n) - 1;
+    let Some(mut last) = (0..n)
+    .filter_map(|last| Some((add(best[full][last], from_waypoint[last][end])?, last)))
+    .min()
+    .map(|(_, last)| last) else { return waypoints.to_vec(); }; // Something's unreachable, keep the given order
+
+    let mut order = Vec::new();
+    let mut mask = full;
+    while mask != 0
+    {
+        order.push(waypoints[last]);
+        let prev = previous[mask][last];
+        mask &= !(1 << last);
+        last = prev;
+    }
+    order.reverse();
+    order
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::puzzle::{Door, Key};
+
+    fn open(grid: &mut [Cell], cell: usize, dir: Dir, grid_config: &GridConfig)
+    {
+        let other = neighbour(cell, &dir, grid_config.grid_width, grid_config.grid_size).unwrap();
+        grid[cell].set_wall(&dir, false);
+        grid[other].set_wall(&opposite(&dir), false);
+    }
+
+    // 0 1 2    start 0, end 1, locked door between them
+    // 3 4 5    the key is at 2, the way around to it goes through 4
+    #[test]
+    fn solver_fetches_keys_before_doors()
+    {
+        let grid_config = GridConfig::new(0.0, 0.0, 3, 2, 1.0, (0.0, 0.0));
+        let mut grid = vec![Cell::new(); grid_config.grid_size];
+        open(&mut grid, 0, Dir::Right, &grid_config);
+        open(&mut grid, 1, Dir::Right, &grid_config);
+        open(&mut grid, 0, Dir::Down, &grid_config);
+        open(&mut grid, 3, Dir::Right, &grid_config);
+        open(&mut grid, 4, Dir::Right, &grid_config);
+        open(&mut grid, 5, Dir::Up, &grid_config);
+
+        let puzzle = Puzzle
+        {
+            doors: vec![Door { cell: 0, dir: Dir::Right, other: 1, key: 0 }],
+            keys: vec![Key { cell: 2, id: 0 }],
+            ..Puzzle::default()
+        };
+
+        let mut stops = key_stops(0, 1, &grid, &puzzle, &grid_config);
+        assert_eq!(stops, vec![2]);
+        stops.push(1);
+
+        for algorithm in SolverAlgorithm::ALL
+        {
+            let mut solver = Solver::new(0, 1, &grid_config);
+            solver.algorithm = algorithm;
+            solver.puzzle = puzzle.clone();
+            solver.redo_route(0, stops.clone(), &grid);
+            solver.solve(&grid, &grid_config);
+
+            assert!(solver.on_path[4], "{} went through the door without the key", algorithm.name());
+            assert!(solver.on_path[2], "{} didn't fetch the key", algorithm.name());
+        }
+
+        // Without the puzzle it goes straight through
+        let mut solver = Solver::new(0, 1, &grid_config);
+        solver.redo(0, 1, &grid);
+        solver.solve(&grid, &grid_config);
+        assert!(!solver.on_path[4]);
+    }
+}
@@ -0,0 +1,101 @@
+use std::f32::consts::TAU;
+
+use macroquad::prelude::*;
+
+pub const MAX_FOLDS: u8 = 12;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Symmetry
+{
+    None,
+    Vertical,   // Mirrored across the vertical axis, left and right match
+    Horizontal, // Top and bottom match
+    Both,
+    Rotational(u8), // N copies around the center
+}
+
+impl Symmetry
+{
+    // What the maze view offers, a grid only has quarter turns
+    pub const GRID: [Symmetry; 6] = [Symmetry::None, Symmetry::Vertical, Symmetry::Horizontal, Symmetry::Both, Symmetry::Rotational(2), Symmetry::Rotational(4)];
+
+    pub fn name(self) -> String
+    {
+        match self
+        {
+            Symmetry::None => "None".to_owned(),
+            Symmetry::Vertical => "Left / Right".to_owned(),
+            Symmetry::Horizontal => "Top / Bottom".to_owned(),
+            Symmetry::Both => "Four Way".to_owned(),
+            Symmetry::Rotational(folds) => format!("Rotate {}x", folds),
+        }
+    }
+
+    // Every copy of a canvas point, the point itself first
+    pub fn points(self, point: Vec2, center: Vec2) -> Vec<Vec2>
+    {
+        let mirrored = center * 2.0 - point;
+
+        match self
+        {
+            Symmetry::None => vec![point],
+            Symmetry::Vertical => vec![point, vec2(mirrored.x, point.y)],
+            Symmetry::Horizontal => vec![point, vec2(point.x, mirrored.y)],
+            Symmetry::Both => vec![point, vec2(mirrored.x, point.y), vec2(point.x, mirrored.y), mirrored],
+            Symmetry::Rotational(folds) => (0..folds.max(1)).map(|i|
+            {
+                let angle = Vec2::from_angle(TAU * i as f32 / folds as f32);
+                center + angle.rotate(point - center)
+            }).collect(),
+        }
+    }
+
+    // Lines through the center the copies are mirrored across or turned around
+    pub fn guides(self, center: Vec2, reach: f32) -> Vec<(Vec2, Vec2)>
+    {
+        let vertical = (center - vec2(0.0, reach), center + vec2(0.0, reach));
+        let horizontal = (center - vec2(reach, 0.0), center + vec2(reach, 0.0));
+
+        match self
+        {
+            Symmetry::None => Vec::new(),
+            Symmetry::Vertical => vec![vertical],
+            Symmetry::Horizontal => vec![horizontal],
+            Symmetry::Both => vec![vertical, horizontal],
+            Symmetry::Rotational(folds) => (0..folds.max(1)).map(|i|
+            {
+                (center, center + Vec2::from_angle(TAU * i as f32 / folds as f32 - TAU * 0.25) * reach)
+            }).collect(),
+        }
+    }
+
+    // What the grid can actually hold, quarter turns need a square grid and other turns fall back to none
+    pub fn for_grid(self, grid_width: usize, grid_height: usize) -> Symmetry
+    {
+        match self
+        {
+            Symmetry::Rotational(4) if grid_width != grid_height => Symmetry::Rotational(2),
+            Symmetry::Rotational(2 | 4) | Symmetry::None | Symmetry::Vertical | Symmetry::Horizontal | Symmetry::Both => self,
+            Symmetry::Rotational(_) => Symmetry::None,
+        }
+    }
+
+    // Every copy of a cell, always in the same order, so copies of neighbours are neighbours
+    // Expects a symmetry for_grid allows
+    pub fn cells(self, cell: usize, grid_width: usize, grid_height: usize) -> Vec<usize>
+    {
+        let (x, y) = (cell % grid_width, cell / grid_width);
+        let (fx, fy) = (grid_width - 1 - x, grid_height - 1 - y);
+        let at = |(x, y): (usize, usize)| y * grid_width + x;
+
+        match self
+        {
+            Symmetry::Vertical => vec![cell, at((fx, y))],
+            Symmetry::Horizontal => vec![cell, at((x, fy))],
+            Symmetry::Both => vec![cell, at((fx, y)), at((x, fy)), at((fx, fy))],
+            Symmetry::Rotational(2) => vec![cell, at((fx, fy))],
+            Symmetry::Rotational(4) => vec![cell, at((fy, x)), at((fx, fy)), at((y, fx))],
+            _ => vec![cell],
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{GridConfig, maze::{Cell, Dir, neighbour, opposite}};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SolverAlgorithm
+{
+    Bfs,
+    DeadEndFilling,
+    LeftHand,
+    RightHand,
+    Tremaux,
+    Pledge,
+}
+
+impl SolverAlgorithm
+{
+    pub const ALL: [SolverAlgorithm; 6] =
+    [
+        SolverAlgorithm::Bfs,
+        SolverAlgorithm::DeadEndFilling,
+        SolverAlgorithm::LeftHand,
+        SolverAlgorithm::RightHand,
+        SolverAlgorithm::Tremaux,
+        SolverAlgorithm::Pledge,
+    ];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            SolverAlgorithm::Bfs => "Breadth First",
+            SolverAlgorithm::DeadEndFilling => "Dead-End Filling",
+            SolverAlgorithm::LeftHand => "Left Hand",
+            SolverAlgorithm::RightHand => "Right Hand",
+            SolverAlgorithm::Tremaux => "Trémaux",
+            SolverAlgorithm::Pledge => "Pledge",
+        }
+    }
+}
+
+// The ways a person would go through a maze, one step at a time
+// Only BFS lives in Solver itself, everything else runs through this for one leg
+pub struct Technique
+{
+    pub algorithm: SolverAlgorithm,
+    start: usize,
+    end: usize,
+    started: bool,
+    pub done: bool,
+    pub failed: Option<&'static str>, // Why there's no route
+
+    // Walkers (wall followers, Trémaux, Pledge)
+    pub pos: usize,
+    pub facing: Dir,
+    pub trail: Vec<u32>, // How often each cell was walked through
+    seen_states: HashSet<(usize, Dir, i32)>,
+
+    // Dead-end filling
+    pub filled: Vec<bool>,
+    dead_ends: VecDeque<usize>,
+
+    // Trémaux, marks per passage
+    pub marks: HashMap<(usize, usize), u8>,
+    came_from: Option<usize>,
+
+    // Pledge
+    pub turns: i32, // Sum of turns since hitting the wall, right is +1
+    heading: Dir,
+    following: bool,
+
+    pub route: Vec<usize>,
+    pub changed: Vec<usize>, // Picked up by the solver for redrawing
+}
+
+impl Technique
+{
+    pub fn new(algorithm: SolverAlgorithm, start: usize, end: usize, grid_size: usize) -> Self
+    {
+        let mut trail = vec![0; grid_size];
+        trail[start] = 1;
+
+        Technique
+        {
+            algorithm,
+            start,
+            end,
+            started: false,
+            done: false,
+            failed: None,
+            pos: start,
+            facing: Dir::Right,
+            trail,
+            seen_states: HashSet::new(),
+            filled: vec![false; grid_size],
+            dead_ends: VecDeque::new(),
+            marks: HashMap::new(),
+            came_from: None,
+            turns: 0,
+            heading: Dir::Right,
+            following: false,
+            route: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+
+    pub fn step(&mut self, grid: &[Cell], grid_config: &GridConfig)
+    {
+        if self.done { return; }
+        if !self.started { self.begin(grid, grid_config); }
+
+        if self.algorithm == SolverAlgorithm::DeadEndFilling
+        {
+            self.fill_step(grid, grid_config);
+            return;
+        }
+
+        if self.pos == self.end
+        {
+            // Whatever was walked, minus the detours
+            let walked: Vec<bool> = self.trail.iter().map(|&count| count > 0).collect();
+            self.finish(grid, &walked, grid_config);
+            return;
+        }
+
+        match self.algorithm
+        {
+            SolverAlgorithm::LeftHand => self.follow_wall(false, grid, grid_config),
+            SolverAlgorithm::RightHand => self.follow_wall(true, grid, grid_config),
+            SolverAlgorithm::Tremaux => self.tremaux_step(grid, grid_config),
+            SolverAlgorithm::Pledge => self.pledge_step(grid, grid_config),
+            _ => {},
+        }
+    }
+
+    // Pledge heads for the end's general direction, dead-end filling looks for its first dead ends
+    fn begin(&mut self, grid: &[Cell], grid_config: &GridConfig)
+    {
+        self.started = true;
+
+        let grid_width = grid_config.grid_width as isize;
+        let (dx, dy) = (self.end as isize % grid_width - self.start as isize % grid_width, self.end as isize / grid_width - self.start as isize / grid_width);
+        self.heading = if dx.abs() >= dy.abs() { if dx >= 0 { Dir::Right } else { Dir::Left } } else if dy >= 0 { Dir::Down } else { Dir::Up };
+        self.facing = self.heading;
+
+        if self.algorithm == SolverAlgorithm::DeadEndFilling
+        {
+            self.dead_ends = (0..grid_config.grid_size).filter(|&cell| self.is_dead_end(cell, grid, grid_config)).collect();
+        }
+    }
+
+    fn finish(&mut self, grid: &[Cell], allowed: &[bool], grid_config: &GridConfig)
+    {
+        self.done = true;
+        match path_through(self.start, self.end, allowed, grid, grid_config)
+        {
+            Some(route) => self.route = route,
+            None => self.failed = Some("No way through"),
+        }
+    }
+
+    fn fail(&mut self, reason: &'static str)
+    {
+        self.done = true;
+        self.failed = Some(reason);
+    }
+
+    fn walk(&mut self, dir: Dir, grid_config: &GridConfig)
+    {
+        let Some(next) = neighbour(self.pos, &dir, grid_config.grid_width, grid_config.grid_size) else { return; };
+
+        self.came_from = Some(self.pos);
+        self.pos = next;
+        self.facing = dir;
+        self.trail[next] += 1;
+        self.changed.push(next);
+    }
+
+    // Hand on one wall, never let go. Runs forever around an island, so repeated states count as a loop
+    fn follow_wall(&mut self, right_hand: bool, grid: &[Cell], grid_config: &GridConfig)
+    {
+        if !self.seen_states.insert((self.pos, self.facing, 0))
+        {
+            self.fail("Loop detected, the wall never leads to the end");
+            return;
+        }
+
+        let hand = if right_hand { turn_right(self.facing) } else { turn_left(self.facing) };
+        let other = opposite(&hand);
+        let order = [hand, self.facing, other, opposite(&self.facing)];
+
+        match order.into_iter().find(|dir| is_open(self.pos, dir, grid, grid_config))
+        {
+            Some(dir) => self.walk(dir, grid_config),
+            None => self.fail("Walled in"),
+        }
+    }
+
+    // Mark every passage when going through, never take one with two marks
+    // Coming into a known junction through a new passage means turning around
+    fn tremaux_step(&mut self, grid: &[Cell], grid_config: &GridConfig)
+    {
+        let open: Vec<(Dir, usize)> = [Dir::Up, Dir::Right, Dir::Down, Dir::Left].into_iter()
+        .filter(|dir| is_open(self.pos, dir, grid, grid_config))
+        .filter_map(|dir| Some((dir, neighbour(self.pos, &dir, grid_config.grid_width, grid_config.grid_size)?)))
+        .collect();
+
+        let marks_to = |next: usize| self.marks.get(&edge(self.pos, next)).copied().unwrap_or(0);
+        let entrance = self.came_from.map_or(0, marks_to);
+        let others: Vec<(Dir, usize)> = open.iter().copied().filter(|(_, next)| Some(*next) != self.came_from).collect();
+        let back = open.iter().copied().find(|(_, next)| Some(*next) == self.came_from);
+
+        let known_junction = others.len() > 1 && others.iter().any(|(_, next)| marks_to(*next) > 0);
+        let choice = if known_junction && entrance == 1 { back }
+        else
+        {
+            others.iter().copied()
+            .filter(|(_, next)| marks_to(*next) < 2)
+            .min_by_key(|(_, next)| marks_to(*next))
+            .or(back.filter(|_| entrance < 2))
+        };
+
+        let Some((dir, next)) = choice else
+        {
+            self.fail("Every passage is marked twice");
+            return;
+        };
+
+        *self.marks.entry(edge(self.pos, next)).or_insert(0) += 1;
+        self.walk(dir, grid_config);
+    }
+
+    // Go straight in one heading, when blocked follow the wall (right hand) until the turns add up to zero again
+    fn pledge_step(&mut self, grid: &[Cell], grid_config: &GridConfig)
+    {
+        if !self.seen_states.insert((self.pos, self.facing, self.turns)) || self.seen_states.len() > grid_config.grid_size * 16
+        {
+            self.fail("Loop detected, the heading never leads to the end");
+            return;
+        }
+
+        if !self.following
+        {
+            if is_open(self.pos, &self.heading, grid, grid_config)
+            {
+                self.walk(self.heading, grid_config);
+                return;
+            }
+
+            // Turn left until the wall is on the right
+            self.following = true;
+            self.facing = self.heading;
+            for _ in 0..4
+            {
+                if is_open(self.pos, &self.facing, grid, grid_config) { break; }
+                self.facing = turn_left(self.facing);
+                self.turns -= 1;
+            }
+            if !is_open(self.pos, &self.facing, grid, grid_config)
+            {
+                self.fail("Walled in");
+                return;
+            }
+            self.walk(self.facing, grid_config);
+        }
+        else
+        {
+            let order = [(turn_right(self.facing), 1), (self.facing, 0), (turn_left(self.facing), -1), (opposite(&self.facing), -2)];
+            let Some((dir, turn)) = order.into_iter().find(|(dir, _)| is_open(self.pos, dir, grid, grid_config)) else
+            {
+                self.fail("Walled in");
+                return;
+            };
+            self.turns += turn;
+            self.walk(dir, grid_config);
+        }
+
+        if self.turns == 0 { self.following = false; }
+    }
+
+    // Fills one dead end per step, whatever never gets filled is the way through
+    fn fill_step(&mut self, grid: &[Cell], grid_config: &GridConfig)
+    {
+        while let Some(cell) = self.dead_ends.pop_front()
+        {
+            if self.filled[cell] || !self.is_dead_end(cell, grid, grid_config) { continue; }
+
+            self.filled[cell] = true;
+            self.changed.push(cell);
+            for next in self.open_neighbours(cell, grid, grid_config)
+            {
+                if self.is_dead_end(next, grid, grid_config) { self.dead_ends.push_back(next); }
+            }
+            return;
+        }
+
+        // Loops from image carving survive the filling, BFS picks the way through what's left
+        let allowed: Vec<bool> = self.filled.iter().map(|filled| !filled).collect();
+        self.finish(grid, &allowed, grid_config);
+    }
+
+    fn open_neighbours(&self, cell: usize, grid: &[Cell], grid_config: &GridConfig) -> Vec<usize>
+    {
+        [Dir::Up, Dir::Right, Dir::Down, Dir::Left].iter()
+        .filter(|dir| is_open(cell, dir, grid, grid_config))
+        .filter_map(|dir| neighbour(cell, dir, grid_config.grid_width, grid_config.grid_size))
+        .filter(|next| !self.filled[*next])
+        .collect()
+    }
+
+    fn is_dead_end(&self, cell: usize, grid: &[Cell], grid_config: &GridConfig) -> bool
+    {
+        cell != self.start && cell != self.end && !self.filled[cell] && self.open_neighbours(cell, grid, grid_config).len() <= 1
+    }
+}
+
+fn is_open(cell: usize, dir: &Dir, grid: &[Cell], grid_config: &GridConfig) -> bool
+{
+    !grid[cell].has_wall(dir) && neighbour(cell, dir, grid_config.grid_width, grid_config.grid_size).is_some()
+}
+
+fn edge(a: usize, b: usize) -> (usize, usize)
+{
+    (a.min(b), a.max(b))
+}
+
+pub fn turn_right(dir: Dir) -> Dir
+{
+    match dir { Dir::Up => Dir::Right, Dir::Right => Dir::Down, Dir::Down => Dir::Left, Dir::Left => Dir::Up }
+}
+
+pub fn turn_left(dir: Dir) -> Dir
+{
+    match dir { Dir::Up => Dir::Left, Dir::Left => Dir::Down, Dir::Down => Dir::Right, Dir::Right => Dir::Up }
+}
+
+// BFS that only goes through allowed cells
+fn path_through(start: usize, end: usize, allowed: &[bool], grid: &[Cell], grid_config: &GridConfig) -> Option<Vec<usize>>
+{
+    let mut previous = vec![None; grid_config.grid_size];
+    let mut queue = VecDeque::from([start]);
+    previous[start] = Some(start);
+
+    while let Some(cell) = queue.pop_front()
+    {
+        if cell == end
+        {
+            let mut path = vec![end];
+            let mut pos = end;
+            while pos != start
+            {
+                pos = previous[pos]?;
+                path.push(pos);
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for dir in [Dir::Up, Dir::Right, Dir::Down, Dir::Left]
+        {
+            if !is_open(cell, &dir, grid, grid_config) { continue; }
+            let Some(next) = neighbour(cell, &dir, grid_config.grid_width, grid_config.grid_size) else { continue; };
+
+            if allowed[next] && previous[next].is_none()
+            {
+                previous[next] = Some(cell);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
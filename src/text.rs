@@ -0,0 +1,450 @@
+use std::collections::VecDeque;
+
+use egui_macroquad::egui;
+use fontdue::{Font, FontSettings};
+
+use crate::{GridConfig, generator::find_path_component, maze::{Dir, neighbour}};
+
+const MARGIN: usize = 1; // Cells kept free around the text
+const MAX_SPUR: usize = 2; // Stubs this short are left over from thinning, not part of a letter
+const BUNDLED_FONT: &str = "Hack";
+
+// The typed text as a path mask for generation, thin, 4-connected and all letters joined into one route
+// Stubs get pruned before the joining, so the bridges and what they join (like the dot of an i) stay
+// An empty font path uses the bundled font
+pub fn text_path(text: &str, font_path: &str, grid_config: &GridConfig) -> Result<Vec<bool>, String>
+{
+    if text.trim().is_empty() { return Err("No text to draw".to_owned()); }
+
+    let font = load_font(font_path)?;
+    let mut path = rasterize(text, &font, grid_config)?;
+
+    thin(&mut path, grid_config.grid_width);
+    connect_diagonals(&mut path, grid_config.grid_width);
+    prune_spurs(&mut path, grid_config);
+    join_components(&mut path, grid_config);
+
+    Ok(path)
+}
+
+fn load_font(font_path: &str) -> Result<Font, String>
+{
+    if font_path.is_empty()
+    {
+        let fonts = egui::FontDefinitions::default();
+        let data = fonts.font_data.get(BUNDLED_FONT).ok_or("Bundled font missing")?;
+        return Font::from_bytes(&data.font[..], FontSettings::default()).map_err(str::to_owned);
+    }
+
+    let bytes = std::fs::read(font_path).map_err(|e| e.to_string())?;
+    Font::from_bytes(bytes, FontSettings::default()).map_err(str::to_owned)
+}
+
+// Biggest font size that still fits the grid, one pixel per cell
+fn rasterize(text: &str, font: &Font, grid_config: &GridConfig) -> Result<Vec<bool>, String>
+{
+    let (grid_width, grid_height) = (grid_config.grid_width, grid_config.grid_height);
+    let lines: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+
+    let available_w = grid_width.saturating_sub(MARGIN * 2) as f32;
+    let available_h = grid_height.saturating_sub(MARGIN * 2) as f32;
+
+    // Everything scales with the size, so measuring once is enough
+    const REFERENCE: f32 = 100.0;
+    let line_width = |line: &str, px: f32| line.chars().map(|c| font.metrics(c, px).advance_width).sum::<f32>();
+    let line_height = |px: f32| font.horizontal_line_metrics(px).map_or(px, |m| m.ascent - m.descent);
+
+    let widest = lines.iter().map(|line| line_width(line, REFERENCE)).fold(0.0, f32::max);
+    let scale = (available_w / widest).min(available_h / (line_height(REFERENCE) * lines.len() as f32));
+    let px = REFERENCE * scale;
+    if !px.is_finite() || px < 4.0 { return Err("Grid is too small for this text".to_owned()); }
+
+    let ascent = font.horizontal_line_metrics(px).map_or(px, |m| m.ascent);
+    let total_height = line_height(px) * lines.len() as f32;
+    let top = MARGIN as f32 + (available_h - total_height) * 0.5;
+
+    let mut path = vec![false; grid_config.grid_size];
+    for (row, line) in lines.iter().enumerate()
+    {
+        let baseline = top + line_height(px) * row as f32 + ascent;
+        let mut x = MARGIN as f32 + (available_w - line_width(line, px)) * 0.5;
+
+        for c in line.chars()
+        {
+            let (metrics, coverage) = font.rasterize(c, px);
+            let left = (x + metrics.xmin as f32).round() as isize;
+            let glyph_top = (baseline - metrics.ymin as f32 - metrics.height as f32).round() as isize;
+
+            for gy in 0..metrics.height
+            {
+                for gx in 0..metrics.width
+                {
+                    if coverage[gy * metrics.width + gx] < 128 { continue; }
+
+                    let (cx, cy) = (left + gx as isize, glyph_top + gy as isize);
+                    if cx < 0 || cy < 0 || cx as usize >= grid_width || cy as usize >= grid_height { continue; }
+                    path[cy as usize * grid_width + cx as usize] = true;
+                }
+            }
+            x += metrics.advance_width;
+        }
+    }
+
+    if !path.contains(&true) { return Err("Text came out empty at this grid size".to_owned()); }
+    Ok(path)
+}
+
+// Zhang-Suen thinning, strokes end up one cell wide
+fn thin(path: &mut [bool], grid_width: usize)
+{
+    let grid_height = path.len() / grid_width;
+    let at = |path: &[bool], x: isize, y: isize| x >= 0 && y >= 0 && (x as usize) < grid_width && (y as usize) < grid_height && path[y as usize * grid_width + x as usize];
+
+    loop
+    {
+        let mut changed = false;
+
+        for pass in 0..2
+        {
+            let mut remove = Vec::new();
+
+            for i in (0..path.len()).filter(|&i| path[i])
+            {
+                let (x, y) = ((i % grid_width) as isize, (i / grid_width) as isize);
+
+                // Clockwise from above
+                let n = [at(path, x, y - 1), at(path, x + 1, y - 1), at(path, x + 1, y), at(path, x + 1, y + 1),
+                    at(path, x, y + 1), at(path, x - 1, y + 1), at(path, x - 1, y), at(path, x - 1, y - 1)];
+
+                let count = n.iter().filter(|&&set| set).count();
+                let transitions = (0..8).filter(|&k| !n[k] && n[(k + 1) % 8]).count();
+
+                // First pass peels off the bottom right, the second the top left
+                let outer = if pass == 0 { !(n[2] && n[4] && (n[0] || n[6])) } else { !(n[0] && n[6] && (n[2] || n[4])) };
+
+                if (2..=6).contains(&count) && transitions == 1 && outer { remove.push(i); }
+            }
+
+            changed |= !remove.is_empty();
+            for i in remove { path[i] = false; }
+        }
+
+        if !changed { break; }
+    }
+}
+
+// Thinning leaves diagonal steps, the path search only walks straight
+fn connect_diagonals(path: &mut [bool], grid_width: usize)
+{
+    let grid_height = path.len() / grid_width;
+
+    for y in 0..grid_height.saturating_sub(1)
+    {
+        for x in 0..grid_width
+        {
+            let i = y * grid_width + x;
+            if !path[i] { continue; }
+
+            // Down right, then down left
+            for (dx, corner) in [(1isize, i + 1), (-1, i.wrapping_sub(1))]
+            {
+                let nx = x as isize + dx;
+                if nx < 0 || nx as usize >= grid_width { continue; }
+
+                let diagonal = (y + 1) * grid_width + nx as usize;
+                if path[diagonal] && !path[corner] && !path[i + grid_width] { path[corner] = true; }
+            }
+        }
+    }
+}
+
+// Chains the letters into one route, end to end: each bridge leaves the last letter at the far end of its
+// longest stroke and arrives at an end of the next closest letter's longest stroke
+// That way the route through all letters is the longest path in the mask, which is what the ends get placed on
+// Bridges don't touch any other cell of the text, if there's no room for that the gap gets bridged anywhere
+fn join_components(path: &mut [bool], grid_config: &GridConfig)
+{
+    let (grid_width, grid_size) = (grid_config.grid_width, grid_config.grid_size);
+
+    let components = find_path_component(path, grid_width, grid_size);
+    if components.len() < 2 { return; }
+
+    let mut component_of = vec![None; grid_size];
+    for (k, component) in components.iter().enumerate()
+    {
+        for &i in component.iter() { component_of[i] = Some(k); }
+    }
+    let stroke_ends: Vec<(usize, usize)> = components.iter().map(|component| longest_stroke(path, component[0], grid_width, grid_size)).collect();
+
+    // Leftmost letter first, so the route reads along the text
+    let first = (0..components.len()).min_by_key(|&k| components[k].iter().map(|&i| i % grid_width).min()).unwrap_or(0);
+    let (a, b) = stroke_ends[first];
+    let mut exit = if a % grid_width <= b % grid_width { b } else { a };
+    let mut joined = vec![false; components.len()];
+    joined[first] = true;
+
+    while joined.contains(&false)
+    {
+        let is_target = |cell: usize| component_of[cell].is_some_and(|k| !joined[k] && (stroke_ends[k].0 == cell || stroke_ends[k].1 == cell));
+        let entry = bridge_to_end(path, exit, is_target, grid_width, grid_size)
+        .or_else(|| bridge_anywhere(path, &component_of, &joined, grid_width, grid_size));
+        let Some(entry) = entry else { break; };
+
+        let Some(k) = component_of[entry] else { break; };
+        joined[k] = true;
+
+        // Out through the other end of the longest stroke, the one farther from where the bridge came in
+        let distance = stroke_distances(path, entry, grid_width, grid_size);
+        let (a, b) = stroke_ends[k];
+        exit = if distance[a] >= distance[b] { a } else { b };
+    }
+}
+
+// Bridge from the exit to the end of another letter, through cells that touch no other part of the text
+// Returns the end it arrived at
+fn bridge_to_end(path: &mut [bool], exit: usize, is_target: impl Fn(usize) -> bool, grid_width: usize, grid_size: usize) -> Option<usize>
+{
+    let dirs = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+    let text_neighbours = |cell: usize| dirs.iter().filter_map(|dir| neighbour(cell, dir, grid_width, grid_size)).filter(|&n| path[n] && n != exit).collect::<Vec<_>>();
+
+    let mut came_from: Vec<Option<usize>> = vec![None; grid_size];
+    let mut seen = vec![false; grid_size];
+    let mut queue = VecDeque::from([exit]);
+    seen[exit] = true;
+
+    while let Some(cell) = queue.pop_front()
+    {
+        // A bridge cell next to a target end, that's where it arrives
+        if cell != exit
+        && let [end] = text_neighbours(cell)[..]
+        && is_target(end)
+        {
+            let mut i = Some(cell);
+            while let Some(step) = i.filter(|&step| step != exit)
+            {
+                path[step] = true;
+                i = came_from[step];
+            }
+            return Some(end);
+        }
+
+        for dir in dirs.iter()
+        {
+            let Some(next) = neighbour(cell, dir, grid_width, grid_size) else { continue; };
+            if seen[next] || path[next] { continue; }
+            seen[next] = true;
+
+            let touching = text_neighbours(next);
+            if touching.len() > 1 || touching.first().is_some_and(|&n| !is_target(n)) { continue; }
+
+            came_from[next] = Some(cell);
+            queue.push_back(next);
+        }
+    }
+
+    None
+}
+
+// Shortest gap from anything joined to any other letter, returns the cell it arrived at
+fn bridge_anywhere(path: &mut [bool], component_of: &[Option<usize>], joined: &[bool], grid_width: usize, grid_size: usize) -> Option<usize>
+{
+    let dirs = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+    let on_route: Vec<bool> = (0..grid_size).map(|i| path[i] && component_of[i].is_none_or(|k| joined[k])).collect(); // Earlier bridges have no component
+
+    let mut came_from: Vec<Option<usize>> = vec![None; grid_size];
+    let mut seen = on_route.clone();
+    let mut queue: VecDeque<usize> = (0..grid_size).filter(|&i| seen[i]).collect();
+
+    while let Some(cell) = queue.pop_front()
+    {
+        for dir in dirs.iter()
+        {
+            let Some(next) = neighbour(cell, dir, grid_width, grid_size) else { continue; };
+            if seen[next] { continue; }
+            seen[next] = true;
+            came_from[next] = Some(cell);
+
+            if path[next]
+            {
+                let mut i = came_from[next];
+                while let Some(step) = i.filter(|&step| !on_route[step])
+                {
+                    path[step] = true;
+                    i = came_from[step];
+                }
+                return Some(next);
+            }
+            queue.push_back(next);
+        }
+    }
+
+    None
+}
+
+// Both ends of the longest stroke in a letter, a double BFS
+fn longest_stroke(path: &[bool], cell: usize, grid_width: usize, grid_size: usize) -> (usize, usize)
+{
+    let farthest = |from: usize|
+    {
+        let distance = stroke_distances(path, from, grid_width, grid_size);
+        (0..grid_size).filter_map(|i| Some((distance[i]?, i))).max().map_or(from, |(_, i)| i)
+    };
+
+    let a = farthest(cell);
+    (a, farthest(a))
+}
+
+// Steps along the text, None for cells it doesn't reach
+fn stroke_distances(path: &[bool], from: usize, grid_width: usize, grid_size: usize) -> Vec<Option<usize>>
+{
+    let mut distance = vec![None; grid_size];
+    let mut queue = VecDeque::from([from]);
+    distance[from] = Some(0);
+
+    while let Some(cell) = queue.pop_front()
+    {
+        let d = distance[cell].unwrap_or(0);
+        for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+        {
+            if let Some(next) = neighbour(cell, &dir, grid_width, grid_size)
+            && path[next] && distance[next].is_none()
+            {
+                distance[next] = Some(d + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    distance
+}
+
+// Removes short stubs ending in a junction, full strokes like the arms of an E stay
+fn prune_spurs(path: &mut [bool], grid_config: &GridConfig)
+{
+    let (grid_width, grid_size) = (grid_config.grid_width, grid_config.grid_size);
+    let dirs = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+    let path_neighbours = |path: &[bool], cell: usize| dirs.iter().filter_map(|dir| neighbour(cell, dir, grid_width, grid_size)).filter(|&n| path[n]).collect::<Vec<_>>();
+
+    let ends: Vec<usize> = (0..grid_size).filter(|&i| path[i] && path_neighbours(path, i).len() == 1).collect();
+    for end in ends
+    {
+        let mut spur = vec![end];
+        let mut previous = None;
+        let mut cell = end;
+
+        loop
+        {
+            let next: Vec<usize> = path_neighbours(path, cell).into_iter().filter(|&n| Some(n) != previous).collect();
+            if next.len() != 1 { break; }
+
+            // Reached a junction, everything before it is the stub
+            if path_neighbours(path, next[0]).len() > 2
+            {
+                if spur.len() <= MAX_SPUR { for &i in spur.iter() { path[i] = false; } }
+                break;
+            }
+            if spur.len() > MAX_SPUR { break; }
+
+            previous = Some(cell);
+            cell = next[0];
+            spur.push(cell);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{ends::{self, EndsSettings, EndsStrategy}, generator::create_maze, image::InputGrid, solver, symmetry::Symmetry};
+
+    fn topmost(path: &[bool], grid_width: usize) -> Option<usize>
+    {
+        path.iter().position(|&set| set).map(|i| i / grid_width)
+    }
+
+    // The dot of an i is its own little part until it gets bridged, pruning mustn't eat it
+    #[test]
+    fn dots_survive()
+    {
+        let grid_config = GridConfig::new(0.0, 0.0, 28, 14, 1.0, (0.0, 0.0));
+        let font = load_font("").unwrap();
+
+        let mut thinned = rasterize("ij", &font, &grid_config).unwrap();
+        thin(&mut thinned, grid_config.grid_width);
+        connect_diagonals(&mut thinned, grid_config.grid_width);
+        assert!(find_path_component(&thinned, grid_config.grid_width, grid_config.grid_size).len() > 2, "expected the dots to be apart");
+
+        let path = text_path("ij", "", &grid_config).unwrap();
+        assert_eq!(topmost(&path, grid_config.grid_width), topmost(&thinned, grid_config.grid_width));
+        assert_eq!(find_path_component(&path, grid_config.grid_width, grid_config.grid_size).len(), 1);
+    }
+
+    // Every area off the letters opens onto them in one place only, so going around the letters never is a shortcut
+    #[test]
+    fn maze_follows_the_letters()
+    {
+        let grid_config = GridConfig::new(0.0, 0.0, 60, 24, 1.0, (0.0, 0.0));
+        let (grid_width, grid_size) = (grid_config.grid_width, grid_config.grid_size);
+        let path = text_path("TEH", "", &grid_config).unwrap();
+        let dirs = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+
+        for seed in [1, 2, 3]
+        {
+            let grid = create_maze(Some(InputGrid::from_path(path.clone())), seed, Symmetry::None, &grid_config);
+            let mut seen = path.clone();
+
+            for first in 0..grid_size
+            {
+                if seen[first] { continue; }
+                seen[first] = true;
+
+                let mut openings = 0;
+                let mut stack = vec![first];
+                while let Some(cell) = stack.pop()
+                {
+                    for dir in dirs.iter().filter(|dir| !grid[cell].has_wall(dir))
+                    {
+                        let Some(next) = neighbour(cell, dir, grid_width, grid_size) else { continue; };
+                        if path[next] { openings += 1; }
+                        else if !seen[next]
+                        {
+                            seen[next] = true;
+                            stack.push(next);
+                        }
+                    }
+                }
+                assert_eq!(openings, 1, "seed {} area at {} opens onto the text {} times", seed, first, openings);
+            }
+        }
+    }
+
+    // The solution between the placed ends runs along the text only and goes through every letter
+    #[test]
+    fn route_goes_through_every_letter()
+    {
+        let grid_config = GridConfig::new(0.0, 0.0, 60, 24, 1.0, (0.0, 0.0));
+        let (grid_width, grid_size) = (grid_config.grid_width, grid_config.grid_size);
+        let font = load_font("").unwrap();
+        let settings = EndsSettings { strategy: EndsStrategy::ImagePath, ..EndsSettings::default() };
+
+        for text in ["TEH", "maze", "Tj", "Hi j", "f;g"]
+        {
+            let mut letters = rasterize(text, &font, &grid_config).unwrap();
+            thin(&mut letters, grid_width);
+            connect_diagonals(&mut letters, grid_width);
+            prune_spurs(&mut letters, &grid_config);
+
+            let path = text_path(text, "", &grid_config).unwrap();
+            let grid = create_maze(Some(InputGrid::from_path(path.clone())), 1, Symmetry::None, &grid_config);
+            let (start, end) = ends::choose_ends(&settings, &grid, Some(&path), &grid_config).unwrap();
+            let route = solver::shortest_path(start, end, &grid, &grid_config).unwrap();
+
+            assert!(route.iter().all(|&i| path[i]), "{} route leaves the text", text);
+            for letter in find_path_component(&letters, grid_width, grid_size)
+            {
+                assert!(letter.iter().any(|i| route.contains(i)), "{} route misses the part at {}", text, letter[0]);
+            }
+        }
+    }
+}
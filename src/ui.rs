@@ -1,10 +1,14 @@
+use std::{path::PathBuf, time::Duration};
+
 use macroquad::prelude::*;
 use egui_macroquad::egui;
 
-use crate::AppState;
+use crate::{AppState, brush::{BrushTip, Stabilizer}, canvas::{BrushSettings, Canvas}, ends::{EndsSettings, EndsStrategy, Side}, fog::{FogMode, FogSettings}, game::Game, heatmap::{HeatmapSettings, Palette}, history::History, image::{ColorRole, ROLE_COLORS, color_role}, layers::LayerSettings, maze::{MAX_TOUR_WAYPOINTS, Maze, WaypointOrder}, puzzle::{MAX_DOORS, PuzzleSettings}, recorder::{RecordFormat, RecordSettings}, shapes::ShapeTool, symmetry::{MAX_FOLDS, Symmetry}, techniques::SolverAlgorithm};
 
 const HOVER_WIDTH: f32 = 130.0;
 const PANEL_HEIGHT: f32 = 250.0;
+const MAX_RECENT_COLORS: usize = 8;
+const VIEW_KEYS_HINT: &str = "F fits, 1 for 100%, Home resets, wheel zooms, middle drag pans";
 
 pub struct UI
 {
@@ -16,6 +20,29 @@ pub struct UI
     commands: Vec<UiCommand>,
     show_grid: bool,
     fill_mode: FillMode,
+    typing: bool,
+    animate_generation: bool,
+    step_ms: u64,
+    steps_per_frame: usize,
+    record_settings: RecordSettings,
+    fog_settings: FogSettings,
+    puzzle_settings: PuzzleSettings,
+    ends_settings: EndsSettings,
+    heatmap_settings: HeatmapSettings,
+    recent_colors: Vec<Color>,
+    shape_tool: ShapeTool,
+    snap_to_cells: bool,
+    maze_text: String,
+    font_path: String, // Empty uses the bundled font
+    symmetry: Symmetry,
+}
+
+impl Default for UI
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
 }
 
 impl UI
@@ -31,26 +58,64 @@ impl UI
             image_strength: 0.1,
             commands: Vec::new(),
             show_grid: false,
-            fill_mode: FillMode::None
+            fill_mode: FillMode::None,
+            typing: false,
+            animate_generation: false,
+            step_ms: 10,
+            steps_per_frame: 1,
+            record_settings: RecordSettings::default(),
+            fog_settings: FogSettings::default(),
+            puzzle_settings: PuzzleSettings::default(),
+            ends_settings: EndsSettings::default(),
+            heatmap_settings: HeatmapSettings::default(),
+            recent_colors: Vec::new(),
+            shape_tool: ShapeTool::Freehand,
+            snap_to_cells: false,
+            maze_text: String::new(),
+            font_path: String::new(),
+            symmetry: Symmetry::None,
         }
     }
 
-    pub fn update(&mut self)
+    pub fn update(&mut self, state: &AppState)
     {
         if is_key_released(KeyCode::Tab)
         {
             self.visible = !self.visible;
         }
+
+        // Text fields handle their own undo
+        if self.typing { return; }
+
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if ctrl && is_key_pressed(KeyCode::Z)
+        {
+            self.commands.push(if shift { UiCommand::Redo } else { UiCommand::Undo });
+        }
+
+        // View keys, for whichever view is shown, play keeps its keys to moving
+        if *state == AppState::Play { return; }
+        if is_key_pressed(KeyCode::F) { self.commands.push(UiCommand::FitView); }
+        if is_key_pressed(KeyCode::Key1) { self.commands.push(UiCommand::ActualSize); }
+        if is_key_pressed(KeyCode::Home) { self.commands.push(UiCommand::ResetView); }
     }
 
-    pub fn draw(&mut self, state: &AppState, brush_size: &mut f32, smoothing: &mut f32, color: Color) -> bool
+    // A text field has the keyboard, keys shouldn't do anything else then
+    pub fn typing(&self) -> bool
+    {
+        self.typing
+    }
+
+    pub fn draw(&mut self, state: &AppState, brush: &mut BrushSettings, history: &History, maze: &Maze, game: &Game, canvas: &Canvas) -> bool
     {
         let mut block_input = false;
 
         let (mx, my) = mouse_position();
         let screen_h = screen_height();
 
-        let center_y = screen_h * 0.5;
+        // Stays on screen when the window gets shorter than the panel
+        let center_y = (screen_h * 0.5).max(PANEL_HEIGHT * 0.5);
         let top = center_y - PANEL_HEIGHT * 0.5;
         let bottom = center_y + PANEL_HEIGHT * 0.5;
 
@@ -59,6 +124,7 @@ impl UI
         egui_macroquad::ui(|egui_ctx| 
         {
             block_input = egui_ctx.wants_pointer_input() || egui_ctx.is_pointer_over_area();
+            self.typing = egui_ctx.wants_keyboard_input();
 
             if self.hovered || self.visible// && !self.visible
             {
@@ -93,6 +159,7 @@ impl UI
                     {
                         AppState::Maze => "Maze",
                         AppState::Draw => "Draw",
+                        AppState::Play => "Play",
                     });
 
                     ui.separator();
@@ -107,14 +174,31 @@ impl UI
                         {
                             self.commands.push(UiCommand::SwitchState(AppState::Draw));
                         }
+                        if ui.selectable_label(*state == AppState::Play, "Play").clicked() 
+                        {
+                            self.commands.push(UiCommand::SwitchState(AppState::Play));
+                        }
+                    });
+
+                    ui.horizontal(|ui|
+                    {
+                        if ui.add_enabled(history.can_undo(), egui::Button::new("Undo")).clicked()
+                        {
+                            self.commands.push(UiCommand::Undo);
+                        }
+                        if ui.add_enabled(history.can_redo(), egui::Button::new("Redo")).clicked()
+                        {
+                            self.commands.push(UiCommand::Redo);
+                        }
                     });
 
                     ui.separator();
 
                     match state
                     {
-                        AppState::Maze => self.maze_ui(ui),
-                        AppState::Draw => self.draw_ui(ui, brush_size, smoothing, color),
+                        AppState::Maze => self.maze_ui(ui, maze),
+                        AppState::Draw => self.draw_ui(ui, brush, canvas),
+                        AppState::Play => self.play_ui(ui, game),
                     }
                     
                     ui.separator();
@@ -140,15 +224,113 @@ impl UI
         block_input
     }
 
-    fn maze_ui(&mut self, ui: &mut egui::Ui)
+    fn maze_ui(&mut self, ui: &mut egui::Ui, maze: &Maze)
     {
-        if ui.button("Regenerate Maze").clicked()
+        let playing = maze.playback.playing;
+
+        ui.horizontal(|ui|
         {
-            self.commands.push(UiCommand::RegenerateMaze
+            if ui.button("Regenerate Maze").clicked()
             {
-                use_image: self.image,
-                threshold: self.image_strength,
+                self.commands.push(UiCommand::RegenerateMaze
+                {
+                    use_image: self.image,
+                    threshold: self.image_strength,
+                    animate: self.animate_generation,
+                });
+            }
+            ui.checkbox(&mut self.animate_generation, "Animate");
+        });
+        ui.horizontal(|ui|
+        {
+            ui.label("Symmetry:");
+            let mut symmetry = maze.symmetry;
+            egui::ComboBox::from_id_salt("maze_symmetry").selected_text(symmetry.name()).show_ui(ui, |ui|
+            {
+                for option in Symmetry::GRID
+                {
+                    ui.selectable_value(&mut symmetry, option, option.name());
+                }
             });
+            if symmetry != maze.symmetry
+            {
+                self.commands.push(UiCommand::SetMazeSymmetry(symmetry));
+            }
+        });
+
+        ui.horizontal(|ui|
+        {
+            ui.label(format!("View: {:.0}%", maze.camera.zoom * 100.0));
+            if ui.button("Fit (F)").clicked()
+            {
+                self.commands.push(UiCommand::FitView);
+            }
+            if ui.button("100% (1)").clicked()
+            {
+                self.commands.push(UiCommand::ActualSize);
+            }
+            if ui.button("Reset (Home)").clicked()
+            {
+                self.commands.push(UiCommand::ResetView);
+            }
+        });
+        ui.label(VIEW_KEYS_HINT);
+
+        // Shared by generation and solving
+        ui.horizontal(|ui|
+        {
+            if ui.button(if playing { "Pause" } else { "Play" }).clicked()
+            {
+                self.commands.push(UiCommand::TogglePlay);
+            }
+            if ui.button("Step Back").clicked()
+            {
+                self.commands.push(UiCommand::StepBack);
+            }
+            if ui.button("Step").clicked()
+            {
+                self.commands.push(UiCommand::StepOnce);
+            }
+            if maze.generator.is_some()
+            {
+                if ui.button("Skip").clicked()
+                {
+                    self.commands.push(UiCommand::FinishGeneration);
+                }
+            }
+            else if ui.button("Solve Instantly").clicked()
+            {
+                self.commands.push(UiCommand::SolveInstantly);
+            }
+        });
+        ui.horizontal(|ui|
+        {
+            ui.label("Solver:");
+            let mut algorithm = maze.solver.algorithm;
+            egui::ComboBox::from_id_salt("solver").selected_text(algorithm.name()).show_ui(ui, |ui|
+            {
+                for option in SolverAlgorithm::ALL
+                {
+                    ui.selectable_value(&mut algorithm, option, option.name());
+                }
+            });
+            if algorithm != maze.solver.algorithm
+            {
+                self.commands.push(UiCommand::SetSolverAlgorithm(algorithm));
+            }
+        });
+        if let Some(technique) = &maze.solver.technique
+        {
+            if let Some(reason) = technique.failed { ui.label(reason); }
+            else if technique.algorithm == SolverAlgorithm::Pledge { ui.label(format!("Turn count: {}", technique.turns)); }
+        }
+        if ui.add(egui::Slider::new(&mut self.step_ms, 0..=500).text("ms per Step")).changed()
+        {
+            self.commands.push(UiCommand::SetStepInterval(Duration::from_millis(self.step_ms)));
+        }
+        if ui.add(egui::Slider::new(&mut self.steps_per_frame, 1..=1000).logarithmic(true).text("Steps per Frame")).changed()
+        {
+            self.commands.push(UiCommand::SetStepsPerFrame(self.steps_per_frame));
         }
 
         ui.separator();
@@ -170,35 +352,386 @@ impl UI
             ui.text_edit_singleline(&mut self.image_path);
 
             if ui.button("Browse").clicked()
-            {
-                if let Some(path) = rfd::FileDialog::new()
+            && let Some(path) = rfd::FileDialog::new()
                 .add_filter("Image files", &["png", "jpg", "jpeg", "bmp", "gif", "tiff"])
                 .pick_file()
+            {
+                self.image_path = path.to_string_lossy().to_string();
+            }
+        });
+
+        ui.add(egui::Slider::new(&mut self.image_strength, 0.0..=1.0).text("Threshold"));
+
+        ui.separator();
+
+        self.ends_ui(ui);
+
+        ui.separator();
+
+        self.heatmap_ui(ui, maze);
+
+        ui.separator();
+
+        self.waypoint_ui(ui, maze);
+
+        ui.separator();
+
+        self.puzzle_ui(ui, maze);
+
+        ui.separator();
+
+        self.record_ui(ui, maze);
+    }
+
+    fn ends_ui(&mut self, ui: &mut egui::Ui)
+    {
+        let old_ends = self.ends_settings;
+        let ends = &mut self.ends_settings;
+
+        ui.horizontal(|ui|
+        {
+            ui.label("Start/End:");
+            ui.selectable_value(&mut ends.strategy, EndsStrategy::Manual, "Manual");
+            ui.selectable_value(&mut ends.strategy, EndsStrategy::Diameter, "Farthest");
+            ui.selectable_value(&mut ends.strategy, EndsStrategy::ImagePath, "Image Path");
+            ui.selectable_value(&mut ends.strategy, EndsStrategy::Sides, "Sides");
+        });
+
+        if ends.strategy == EndsStrategy::Sides
+        {
+            ui.horizontal(|ui|
+            {
+                side_combo(ui, "start_side", &mut ends.start_side);
+                ui.label("to");
+                side_combo(ui, "end_side", &mut ends.end_side);
+            });
+        }
+
+        ui.horizontal(|ui|
+        {
+            ui.add_enabled(ends.strategy != EndsStrategy::Manual, egui::Checkbox::new(&mut ends.open_border, "Open Border"));
+            if ui.add_enabled(ends.strategy != EndsStrategy::Manual, egui::Button::new("Apply")).clicked()
+            {
+                self.commands.push(UiCommand::ApplyEnds);
+            }
+        });
+
+        if *ends != old_ends
+        {
+            self.commands.push(UiCommand::SetEnds(*ends));
+        }
+    }
+
+    fn heatmap_ui(&mut self, ui: &mut egui::Ui, maze: &Maze)
+    {
+        let old_heatmap = self.heatmap_settings;
+        let heatmap = &mut self.heatmap_settings;
+
+        ui.horizontal(|ui|
+        {
+            ui.checkbox(&mut heatmap.enabled, "Distance Heatmap");
+            egui::ComboBox::from_id_salt("palette").selected_text(heatmap.palette.name()).show_ui(ui, |ui|
+            {
+                for option in Palette::ALL
+                {
+                    ui.selectable_value(&mut heatmap.palette, option, option.name());
+                }
+            });
+        });
+
+        if heatmap.enabled
+        {
+            ui.horizontal(|ui|
+            {
+                ui.label(if maze.heatmap.source.is_some() { "From clicked cell (Ctrl + Click)" } else { "From start (Ctrl + Click to change)" });
+                if maze.heatmap.source.is_some() && ui.button("From Start").clicked()
                 {
-                    self.image_path = path.to_string_lossy().to_string();
+                    self.commands.push(UiCommand::ResetHeatmapSource);
                 }
+            });
+
+            // Legend, near to far
+            ui.horizontal(|ui|
+            {
+                ui.label("0");
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(160.0, 14.0), egui::Sense::hover());
+                let steps = 32;
+                for i in 0..steps
+                {
+                    let color = heatmap.palette.color(i as f32 / (steps - 1) as f32);
+                    let x0 = rect.left() + rect.width() * i as f32 / steps as f32;
+                    let x1 = rect.left() + rect.width() * (i + 1) as f32 / steps as f32;
+                    ui.painter().rect_filled(egui::Rect::from_min_max(egui::pos2(x0, rect.top()), egui::pos2(x1, rect.bottom())), 0.0,
+                        to_color32(color));
+                }
+                ui.label(format!("{} steps", maze.heatmap.max_distance));
+            });
+        }
+
+        if *heatmap != old_heatmap
+        {
+            self.commands.push(UiCommand::SetHeatmap(*heatmap));
+        }
+    }
+
+    fn waypoint_ui(&mut self, ui: &mut egui::Ui, maze: &Maze)
+    {
+        ui.label(format!("Waypoints: {} (Shift + Click to add or remove)", maze.waypoints.len()));
+        ui.horizontal(|ui|
+        {
+            let mut order = maze.waypoint_order;
+            ui.selectable_value(&mut order, WaypointOrder::InOrder, "In Order");
+            ui.selectable_value(&mut order, WaypointOrder::ShortestTour, "Shortest Tour");
+            if order != maze.waypoint_order
+            {
+                self.commands.push(UiCommand::SetWaypointOrder(order));
+            }
+
+            if ui.add_enabled(!maze.waypoints.is_empty(), egui::Button::new("Clear")).clicked()
+            {
+                self.commands.push(UiCommand::ClearWaypoints);
             }
         });
 
-        ui.add(egui::Slider::new(&mut self.image_strength, 0.0..=1.0).text("Threshold"));
+        if maze.waypoint_order == WaypointOrder::ShortestTour && maze.waypoints.len() > MAX_TOUR_WAYPOINTS
+        {
+            ui.label(format!("Too many for the shortest tour (max {}), using the placed order", MAX_TOUR_WAYPOINTS));
+        }
     }
 
-    fn draw_ui(&mut self, ui: &mut egui::Ui, brush_size: &mut f32, smoothing: &mut f32, color: Color)
+    fn puzzle_ui(&mut self, ui: &mut egui::Ui, maze: &Maze)
     {
+        ui.add(egui::Slider::new(&mut self.puzzle_settings.doors, 0..=MAX_DOORS).text("Doors"));
+        ui.add(egui::Slider::new(&mut self.puzzle_settings.collectibles, 0..=20).text("Collectibles"));
+
+        ui.horizontal(|ui|
+        {
+            if ui.add_enabled(maze.generator.is_none(), egui::Button::new("Place Puzzle")).clicked()
+            {
+                self.commands.push(UiCommand::PlacePuzzle(self.puzzle_settings));
+            }
+            if ui.add_enabled(!maze.puzzle.is_empty(), egui::Button::new("Clear")).clicked()
+            {
+                self.commands.push(UiCommand::ClearPuzzle);
+            }
+        });
+
+        if !maze.puzzle.is_empty()
+        {
+            ui.label(format!("{} doors, solvable in {} steps", maze.puzzle.doors.len(), maze.puzzle.route_length));
+        }
+    }
+
+    fn record_ui(&mut self, ui: &mut egui::Ui, maze: &Maze)
+    {
+        if let Some(recorder) = &maze.recorder
+        {
+            ui.horizontal(|ui|
+            {
+                ui.label(format!("Recording, {} frames", recorder.frame_count()));
+                if ui.button("Stop & Save").clicked()
+                {
+                    self.commands.push(UiCommand::StopRecording);
+                }
+            });
+            return;
+        }
+
+        let settings = &mut self.record_settings;
+        ui.horizontal(|ui|
+        {
+            ui.selectable_value(&mut settings.format, RecordFormat::Gif, "GIF");
+            ui.selectable_value(&mut settings.format, RecordFormat::Apng, "APNG");
+            ui.selectable_value(&mut settings.format, RecordFormat::PngSequence, "PNGs");
+        });
+        ui.horizontal(|ui|
+        {
+            ui.add(egui::DragValue::new(&mut settings.width).range(16..=4096).suffix(" px"));
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut settings.height).range(16..=4096).suffix(" px"));
+        });
+        ui.add(egui::Slider::new(&mut settings.steps_per_frame, 1..=100).text("Steps per Frame"));
+        ui.add(egui::Slider::new(&mut settings.frame_delay_ms, 10..=1000).text("Frame Delay (ms)"));
+
+        if ui.button("Record").clicked()
+        {
+            let path = match settings.format
+            {
+                RecordFormat::Gif => rfd::FileDialog::new().add_filter("GIF", &["gif"]).set_file_name("maze.gif").save_file(),
+                RecordFormat::Apng => rfd::FileDialog::new().add_filter("APNG", &["png", "apng"]).set_file_name("maze.png").save_file(),
+                RecordFormat::PngSequence => rfd::FileDialog::new().pick_folder(),
+            };
+
+            if let Some(path) = path
+            {
+                self.commands.push(UiCommand::StartRecording { settings: *settings, path });
+            }
+        }
+    }
+
+    fn draw_ui(&mut self, ui: &mut egui::Ui, brush: &mut BrushSettings, canvas: &Canvas)
+    {
+        ui.horizontal(|ui|
+        {
+            if ui.button("Fit (F)").clicked()
+            {
+                self.commands.push(UiCommand::FitView);
+            }
+            if ui.button("100% (1)").clicked()
+            {
+                self.commands.push(UiCommand::ActualSize);
+            }
+            if ui.button("Reset (Home)").clicked()
+            {
+                self.commands.push(UiCommand::ResetView);
+            }
+        });
+        ui.label(VIEW_KEYS_HINT);
+
+        ui.separator();
+
+        self.layers_ui(ui, canvas);
+
+        ui.horizontal(|ui|
+        {
+            if ui.button("Open Image").on_hover_text("Into the selected layer, stretched to the canvas").clicked()
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter("Image files", &["png", "jpg", "jpeg", "bmp", "gif", "tiff"])
+                .pick_file()
+            {
+                self.commands.push(UiCommand::LoadCanvas(path.to_string_lossy().to_string()));
+            }
+            if ui.button("Save Flattened Input").on_hover_text("Only the layers marked Gen, merged into one PNG like generation sees them").clicked()
+            && let Some(path) = rfd::FileDialog::new().add_filter("PNG", &["png"]).set_file_name("input.png").save_file()
+            {
+                self.commands.push(UiCommand::SaveCanvas(path.to_string_lossy().to_string()));
+            }
+        });
+
+        ui.separator();
+
+        let color = brush.color;
+
+        // Remembered once the mouse is let go, so dragging in the picker doesn't fill the list
+        if !brush.eraser && !ui.input(|input| input.pointer.any_down()) && self.recent_colors.first() != Some(&color)
+        {
+            self.recent_colors.retain(|&recent| recent != color);
+            self.recent_colors.insert(0, color);
+            self.recent_colors.truncate(MAX_RECENT_COLORS);
+        }
+
         ui.label("Brush settings");
         ui.horizontal(|ui|
         {
-            if ui.selectable_label(color == WHITE, "White").clicked() 
+            let mut rgb = [color.r, color.g, color.b];
+            if ui.color_edit_button_rgb(&mut rgb).changed()
             {
-                self.commands.push(UiCommand::SwitchColor(WHITE));
+                self.commands.push(UiCommand::SwitchColor(Color::new(rgb[0], rgb[1], rgb[2], 1.0)));
             }
-            if ui.selectable_label(color == BLACK, "Black").clicked() 
+            for (name, role_color) in ROLE_COLORS
             {
-                self.commands.push(UiCommand::SwitchColor(BLACK));
+                if ui.selectable_label(!brush.eraser && color == role_color, name).clicked()
+                {
+                    self.commands.push(UiCommand::SwitchColor(role_color));
+                }
+            }
+        });
+        ui.horizontal(|ui|
+        {
+            for &recent in self.recent_colors.iter()
+            {
+                if ui.add(egui::Button::new("").fill(to_color32(recent)).min_size(egui::vec2(18.0, 18.0))).clicked()
+                {
+                    self.commands.push(UiCommand::SwitchColor(recent));
+                }
+            }
+        });
+        ui.horizontal(|ui|
+        {
+            if ui.selectable_label(brush.eraser, "Eraser").clicked()
+            {
+                self.commands.push(UiCommand::SetEraser(!brush.eraser));
+            }
+            ui.label(match (brush.eraser, color_role(color))
+            {
+                (true, _) | (false, None) => "Draws: nothing",
+                (false, Some(ColorRole::Path)) => "Draws: path",
+                (false, Some(ColorRole::Wall)) => "Draws: walls",
+                (false, Some(ColorRole::Mask)) => "Draws: mask",
+                (false, Some(ColorRole::Weight(_))) => "Draws: weight",
+            });
+        });
+        ui.horizontal_wrapped(|ui|
+        {
+            for tool in ShapeTool::ALL
+            {
+                if ui.selectable_label(self.shape_tool == tool, tool.name()).clicked()
+                {
+                    self.shape_tool = tool;
+                    self.commands.push(UiCommand::SetShapeTool(tool));
+
+                    // Fills would take over the click
+                    if tool != ShapeTool::Freehand && self.fill_mode != FillMode::None
+                    {
+                        self.fill_mode = FillMode::None;
+                        self.commands.push(UiCommand::SwitchFillMode(FillMode::None));
+                    }
+                }
+            }
+        });
+        if self.shape_tool != ShapeTool::Freehand
+        {
+            ui.label(self.shape_tool.hint());
+            if ui.checkbox(&mut self.snap_to_cells, "Snap to Cells").clicked()
+            {
+                self.commands.push(UiCommand::SnapToCells(self.snap_to_cells));
+            }
+        }
+        ui.horizontal(|ui|
+        {
+            let old = self.symmetry;
+            ui.label("Symmetry:");
+            egui::ComboBox::from_id_salt("draw_symmetry").selected_text(self.symmetry.name()).show_ui(ui, |ui|
+            {
+                let folds = match self.symmetry { Symmetry::Rotational(folds) => folds, _ => 6 };
+                for option in [Symmetry::None, Symmetry::Vertical, Symmetry::Horizontal, Symmetry::Both, Symmetry::Rotational(folds)]
+                {
+                    let selected = std::mem::discriminant(&self.symmetry) == std::mem::discriminant(&option);
+                    if ui.selectable_label(selected, if let Symmetry::Rotational(_) = option { "Rotational".to_owned() } else { option.name() }).clicked()
+                    {
+                        self.symmetry = option;
+                    }
+                }
+            });
+            if let Symmetry::Rotational(folds) = &mut self.symmetry
+            {
+                ui.add(egui::Slider::new(folds, 2..=MAX_FOLDS).text("Copies"));
+            }
+            if self.symmetry != old
+            {
+                self.commands.push(UiCommand::SetSymmetry(self.symmetry));
             }
         });
-        ui.add(egui::Slider::new(brush_size, 1.0..=50.0).text("Brush Size"));
-        ui.add(egui::Slider::new(smoothing, 0.005..=1.0).text("Smoothing"));
+        ui.add(egui::Slider::new(&mut brush.size, 1.0..=50.0).text("Brush Size"));
+        ui.horizontal(|ui|
+        {
+            ui.label("Tip:");
+            for tip in BrushTip::ALL
+            {
+                if ui.selectable_label(brush.tip == tip, tip.name()).clicked() { brush.tip = tip; }
+            }
+        });
+        ui.horizontal(|ui|
+        {
+            ui.label("Stabilizer:");
+            for stabilizer in Stabilizer::ALL
+            {
+                if ui.selectable_label(brush.stabilizer == stabilizer, stabilizer.name()).clicked() { brush.stabilizer = stabilizer; }
+            }
+        });
+        ui.add(egui::Slider::new(&mut brush.smoothing, 0.005..=1.0).text("Smoothing"));
+        ui.add(egui::Slider::new(&mut brush.speed_thinning, 0.0..=1.0).text("Speed Thinning"));
+        ui.add(egui::Slider::new(&mut brush.taper, 0.0..=100.0).text("Taper"));
         if ui.checkbox(&mut self.show_grid, "Toggle Grid").clicked()
         {
             self.commands.push(UiCommand::ShowGrid(self.show_grid));
@@ -219,6 +752,146 @@ impl UI
                 self.fill_mode = if grid_fill { FillMode::None } else { FillMode::GridFill };
                 self.commands.push(UiCommand::SwitchFillMode(self.fill_mode));
             }
+
+            let cell_flood = self.fill_mode == FillMode::CellFlood;
+            if ui.selectable_label(cell_flood, "Cell Fill").clicked()
+            {
+                self.fill_mode = if cell_flood { FillMode::None } else { FillMode::CellFlood };
+                self.commands.push(UiCommand::SwitchFillMode(self.fill_mode));
+            }
+
+            let path_pen = self.fill_mode == FillMode::PathPen;
+            if ui.selectable_label(path_pen, "Path Pen").clicked()
+            {
+                self.fill_mode = if path_pen { FillMode::None } else { FillMode::PathPen };
+                self.commands.push(UiCommand::SwitchFillMode(self.fill_mode));
+            }
+        });
+        if matches!(self.fill_mode, FillMode::NormalFill | FillMode::CellFlood)
+        {
+            ui.add(egui::Slider::new(&mut brush.fill_tolerance, 0.0..=1.0).text("Fill Tolerance"));
+            ui.checkbox(&mut brush.fill_diagonal, "Fill Across Corners");
+        }
+
+        ui.separator();
+
+        ui.label("Text (one route through all letters)");
+        ui.add(egui::TextEdit::multiline(&mut self.maze_text).desired_rows(2));
+        ui.horizontal(|ui|
+        {
+            ui.label("Font:");
+            ui.add(egui::TextEdit::singleline(&mut self.font_path).hint_text("bundled").desired_width(120.0));
+            if ui.button("Browse").clicked()
+            && let Some(path) = rfd::FileDialog::new().add_filter("Fonts", &["ttf", "otf"]).pick_file()
+            {
+                self.font_path = path.to_string_lossy().to_string();
+            }
+        });
+        ui.horizontal(|ui|
+        {
+            if ui.button("Draw on Canvas").clicked()
+            {
+                self.commands.push(UiCommand::TextToCanvas { text: self.maze_text.clone(), font: self.font_path.clone() });
+            }
+            if ui.button("Generate Maze").clicked()
+            {
+                self.commands.push(UiCommand::TextToMaze { text: self.maze_text.clone(), font: self.font_path.clone(), animate: self.animate_generation });
+            }
+        });
+    }
+
+    // Top layer first, like it's drawn
+    fn layers_ui(&mut self, ui: &mut egui::Ui, canvas: &Canvas)
+    {
+        ui.label("Layers (drawing goes on the selected one)");
+
+        let order = canvas.layer_order();
+        for (pos, &i) in order.iter().enumerate().rev()
+        {
+            let layer = &canvas.layers()[i];
+            let mut settings = layer.settings;
+
+            ui.horizontal(|ui|
+            {
+                if ui.selectable_label(canvas.active_layer() == i, layer.kind.name()).clicked()
+                {
+                    self.commands.push(UiCommand::SelectLayer(i));
+                }
+                ui.checkbox(&mut settings.visible, "Show");
+                ui.checkbox(&mut settings.feeds_generation, "Gen").on_hover_text("Used as input when generating from the drawing");
+                ui.add(egui::DragValue::new(&mut settings.opacity).range(0.0..=1.0).speed(0.01));
+
+                if ui.add_enabled(pos + 1 < order.len(), egui::Button::new("⏶")).clicked()
+                {
+                    self.commands.push(UiCommand::MoveLayer { layer: i, up: true });
+                }
+                if ui.add_enabled(pos > 0, egui::Button::new("⏷")).clicked()
+                {
+                    self.commands.push(UiCommand::MoveLayer { layer: i, up: false });
+                }
+            });
+
+            if settings != layer.settings
+            {
+                self.commands.push(UiCommand::SetLayer { layer: i, settings });
+            }
+        }
+
+        if ui.button("Load Reference Image").clicked()
+        && let Some(path) = rfd::FileDialog::new()
+            .add_filter("Image files", &["png", "jpg", "jpeg", "bmp", "gif", "tiff"])
+            .pick_file()
+        {
+            self.commands.push(UiCommand::LoadReference(path.to_string_lossy().to_string()));
+        }
+    }
+
+    fn play_ui(&mut self, ui: &mut egui::Ui, game: &Game)
+    {
+        ui.label("Arrow keys or WASD to move");
+        ui.label(format!("Time: {:.2}s", game.elapsed().as_secs_f32()));
+        ui.label(format!("Moves: {}", game.moves));
+        if game.finished.is_some()
+        {
+            ui.label("Finished!");
+        }
+
+        if ui.button("Restart").clicked()
+        {
+            self.commands.push(UiCommand::RestartGame);
+        }
+
+        ui.separator();
+
+        let old_fog = self.fog_settings;
+        let fog = &mut self.fog_settings;
+        ui.checkbox(&mut fog.enabled, "Fog of War");
+        if fog.enabled
+        {
+            ui.horizontal(|ui|
+            {
+                ui.selectable_value(&mut fog.mode, FogMode::LineOfSight, "Line of Sight");
+                ui.selectable_value(&mut fog.mode, FogMode::Radius, "Radius");
+            });
+            ui.add(egui::Slider::new(&mut fog.radius, 1..=20).text("View Radius"));
+            ui.checkbox(&mut fog.reveal_image, "Reveal Image");
+        }
+        if *fog != old_fog
+        {
+            self.commands.push(UiCommand::SetFog(*fog));
+        }
+
+        ui.separator();
+        ui.label("Best times for this maze");
+        egui::Grid::new("scores").striped(true).show(ui, |ui|
+        {
+            for (i, score) in game.scores.iter().enumerate()
+            {
+                ui.label(format!("{}.", i + 1));
+                ui.label(format!("{:.2}s", score.time.as_secs_f32()));
+                ui.label(format!("{} moves", score.moves));
+                ui.end_row();
+            }
         });
     }
 
@@ -236,10 +909,66 @@ impl UI
 pub enum UiCommand
 {
     SwitchState(AppState),
-    RegenerateMaze { use_image: InputImage, threshold: f32 },
+    RegenerateMaze { use_image: InputImage, threshold: f32, animate: bool },
     SwitchColor(Color),
+    SetEraser(bool),
+    SetShapeTool(ShapeTool),
+    SnapToCells(bool),
+    SetSymmetry(Symmetry),
+    SetMazeSymmetry(Symmetry),
+    TextToCanvas { text: String, font: String },
+    TextToMaze { text: String, font: String, animate: bool },
+    SelectLayer(usize),
+    SetLayer { layer: usize, settings: LayerSettings },
+    MoveLayer { layer: usize, up: bool },
+    LoadReference(String),
+    LoadCanvas(String),
+    SaveCanvas(String),
     ShowGrid(bool),
-    SwitchFillMode(FillMode)
+    SwitchFillMode(FillMode),
+    Undo,
+    Redo,
+    TogglePlay,
+    StepOnce,
+    SetStepInterval(Duration),
+    SetStepsPerFrame(usize),
+    SolveInstantly,
+    StepBack,
+    FinishGeneration,
+    StartRecording { settings: RecordSettings, path: PathBuf },
+    StopRecording,
+    RestartGame,
+    SetFog(FogSettings),
+    PlacePuzzle(PuzzleSettings),
+    ClearPuzzle,
+    SetWaypointOrder(WaypointOrder),
+    ClearWaypoints,
+    SetEnds(EndsSettings),
+    ApplyEnds,
+    SetSolverAlgorithm(SolverAlgorithm),
+    SetHeatmap(HeatmapSettings),
+    ResetHeatmapSource,
+    FitView,
+    ActualSize,
+    ResetView,
+}
+
+fn to_color32(color: Color) -> egui::Color32
+{
+    egui::Color32::from_rgb((color.r * 255.0) as u8, (color.g * 255.0) as u8, (color.b * 255.0) as u8)
+}
+
+fn side_combo(ui: &mut egui::Ui, id: &str, side: &mut Side)
+{
+    let name = |side: Side| match side { Side::Top => "Top", Side::Bottom => "Bottom", Side::Left => "Left", Side::Right => "Right" };
+
+    egui::ComboBox::from_id_salt(id).selected_text(name(*side)).show_ui(ui, |ui|
+    {
+        for option in [Side::Top, Side::Bottom, Side::Left, Side::Right]
+        {
+            ui.selectable_value(side, option, name(option));
+        }
+    });
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -255,5 +984,7 @@ pub enum FillMode
 {
     NormalFill,
     GridFill,
+    CellFlood, // Flood fill that colors whole cells
+    PathPen, // Whole cells, always 4-connected
     None,
 }
\ No newline at end of file